@@ -1,3 +1,4 @@
+use crate::storage::FileStorage;
 use crate::{
     account_create_transaction_file, create_rng_from_seed, errors::Error, get_asset_ids,
     non_empty_account_id, save_object, update_account_map, user_secret_account_file,
@@ -34,13 +35,11 @@ pub fn process_create_account(
 
     // Create the account.
     let secret_account = create_secret_account(&mut rng, ticker.clone())?;
-    let valid_asset_ids = get_asset_ids(db_dir.clone())?;
+    let valid_asset_ids = get_asset_ids(&FileStorage::new(db_dir.clone()))?;
 
     let create_account_timer = Instant::now();
     let account_creator = AccountCreator;
-    let mut account_tx = account_creator
-        .create(&secret_account, &valid_asset_ids, &mut rng)
-        .map_err(|error| Error::LibraryError { error })?;
+    let mut account_tx = account_creator.create(&secret_account, &valid_asset_ids, &mut rng)?;
     timing!("account.call_library", create_account_timer, Instant::now(), "tx_id" => tx_id.to_string());
     if cheat {
         // To simplify the cheating selection process, we randomly choose a cheating strategy,
@@ -50,8 +49,7 @@ pub fn process_create_account(
             0 => {
                 info!("CLI log: tx-{}: Cheating by overwriting the asset id of the account. Correct ticker: {} and asset id: {:?}",
                       tx_id, ticker, secret_account.asset_id_witness.value());
-                let cheat_asset_id =
-                    asset_id_from_ticker("CHEAT").map_err(|error| Error::LibraryError { error })?;
+                let cheat_asset_id = asset_id_from_ticker("CHEAT")?;
                 let cheat_asset_id_witness =
                     CommitmentWitness::new(cheat_asset_id.clone().into(), Scalar::random(&mut rng));
                 let cheat_enc_asset_id = secret_account
@@ -120,8 +118,7 @@ fn create_secret_account<R: RngCore + CryptoRng>(
         secret: elg_secret.into(),
     };
 
-    let asset_id =
-        asset_id_from_ticker(&ticker_id).map_err(|error| Error::LibraryError { error })?;
+    let asset_id = asset_id_from_ticker(&ticker_id)?;
     let asset_id_witness = CommitmentWitness::new(asset_id.clone().into(), Scalar::random(rng));
 
     Ok(SecAccount {
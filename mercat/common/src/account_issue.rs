@@ -1,3 +1,4 @@
+use crate::storage::FileStorage;
 use crate::{
     asset_transaction_file, create_rng_from_seed, errors::Error, last_ordering_state, load_object,
     save_object, user_public_account_file, user_secret_account_file, OrderedAssetInstruction,
@@ -58,7 +59,7 @@ pub fn process_issue_asset(
         issuer.clone(),
         issuer_ordered_pub_account.last_processed_tx_counter,
         tx_id,
-        db_dir.clone(),
+        &FileStorage::new(db_dir.clone()),
     )?;
     let next_pending_tx_counter = ordering_state.last_pending_tx_counter + 1;
 
@@ -91,9 +92,8 @@ pub fn process_issue_asset(
     // Initialize the asset issuance process.
     let issuance_init_timer = Instant::now();
     let ctx_issuer = AssetIssuer;
-    let mut asset_tx = ctx_issuer
-        .initialize_asset_transaction(&issuer_account, &[], amount, &mut rng)
-        .map_err(|error| Error::LibraryError { error })?;
+    let mut asset_tx =
+        ctx_issuer.initialize_asset_transaction(&issuer_account, &[], amount, &mut rng)?;
 
     let ordering_state = OrderingState {
         last_processed_tx_counter: issuer_ordered_pub_account.last_processed_tx_counter,
@@ -104,8 +104,7 @@ pub fn process_issue_asset(
     if cheat && cheating_strategy == 1 {
         info!("CLI log: tx-{}: Cheating by overwriting the encrypted issued amount. Correct ticker: {} and encrypted amount: {:?}",
                       tx_id, ticker, &asset_tx.memo.enc_issued_amount);
-        let cheat_asset_id =
-            asset_id_from_ticker("CHEAT").map_err(|error| Error::LibraryError { error })?;
+        let cheat_asset_id = asset_id_from_ticker("CHEAT")?;
         let cheat_asset_id_witness =
             CommitmentWitness::new(cheat_asset_id.clone().into(), Scalar::random(&mut rng));
         let cheat_enc_asset_id = issuer_account
@@ -1,6 +1,7 @@
+use crate::storage::FileStorage;
 use crate::{
     compute_enc_pending_balance, confidential_transaction_file, construct_path,
-    create_rng_from_seed, debug_decrypt, errors::Error, last_ordering_state, load_object,
+    create_rng_from_seed, debug_decrypt_for_log, errors::Error, last_ordering_state, load_object,
     non_empty_account_id, save_object, user_public_account_balance_file, user_public_account_file,
     user_secret_account_file, OrderedPubAccount, OrderedTransferInstruction, OrderingState,
     PrintableAccountId, COMMON_OBJECTS_DIR, MEDIATOR_PUBLIC_ACCOUNT_FILE, OFF_CHAIN_DIR,
@@ -84,7 +85,7 @@ pub fn process_create_tx(
         sender.clone(),
         last_processed_tx_counter,
         tx_id,
-        db_dir.clone(),
+        &FileStorage::new(db_dir.clone()),
     )?;
 
     let pending_balance = compute_enc_pending_balance(
@@ -92,16 +93,17 @@ pub fn process_create_tx(
         ordering_state.clone(),
         last_processed_tx_counter,
         last_processed_account_balance,
+        &FileStorage::new(db_dir.clone()),
         db_dir.clone(),
     )?;
     debug!(
         "------------> initiating transfer tx: {}, pending_balance: {}",
         tx_id,
-        debug_decrypt(
+        debug_decrypt_for_log(
             sender_account.public.enc_asset_id,
             pending_balance.clone(),
             db_dir.clone()
-        )?
+        )
     );
     let next_pending_tx_counter = ordering_state.last_pending_tx_counter + 1;
 
@@ -137,17 +139,15 @@ pub fn process_create_tx(
             owner_enc_pub_key: sender_account.public.owner_enc_pub_key,
         },
     };
-    let mut asset_tx = ctx_sender
-        .create_transaction(
-            &pending_account,
-            &pending_balance,
-            &receiver_account.pub_account,
-            &mediator_account,
-            &[],
-            amount,
-            &mut rng,
-        )
-        .map_err(|error| Error::LibraryError { error })?;
+    let mut asset_tx = ctx_sender.create_transaction(
+        &pending_account,
+        &pending_balance,
+        &receiver_account.pub_account,
+        &mediator_account,
+        &[],
+        amount,
+        &mut rng,
+    )?;
 
     let ordering_state = OrderingState {
         last_processed_tx_counter: sender_ordered_pub_account.last_processed_tx_counter,
@@ -262,7 +262,7 @@ pub fn process_finalize_tx(
         receiver,
         receiver_ordered_pub_account.last_processed_tx_counter,
         tx_id,
-        db_dir.clone(),
+        &FileStorage::new(db_dir.clone()),
     )?;
     let next_pending_tx_counter = ordering_state.last_pending_tx_counter + 1;
 
@@ -291,9 +291,8 @@ pub fn process_finalize_tx(
     // Finalize the transaction.
     let finalize_by_receiver_timer = Instant::now();
     let receiver = CtxReceiver {};
-    let mut asset_tx = receiver
-        .finalize_transaction(tx, receiver_account.clone(), amount, &mut rng)
-        .map_err(|error| Error::LibraryError { error })?;
+    let mut asset_tx =
+        receiver.finalize_transaction(tx, receiver_account.clone(), amount, &mut rng)?;
 
     let ordering_state = OrderingState {
         last_processed_tx_counter: receiver_ordered_pub_account.last_processed_tx_counter,
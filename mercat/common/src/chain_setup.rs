@@ -10,9 +10,7 @@ pub fn process_asset_id_creation(db_dir: PathBuf, ticker_names: Vec<String>) ->
 
     let valid_asset_ids: Vec<AssetId> = ticker_names
         .into_iter()
-        .map(|ticker_name| {
-            asset_id_from_ticker(&ticker_name).map_err(|error| Error::LibraryError { error })
-        })
+        .map(|ticker_name| asset_id_from_ticker(&ticker_name).map_err(Error::from))
         .collect::<Result<Vec<AssetId>, Error>>()?;
 
     let valid_asset_ids = AssetIdList(convert_asset_ids(valid_asset_ids));
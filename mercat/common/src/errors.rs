@@ -1,6 +1,6 @@
 use crate::CoreTransaction;
 use failure::Fail;
-use std::path::PathBuf;
+use std::{io::ErrorKind, path::PathBuf};
 
 /// Common errors for all mercat clis
 #[derive(Fail, Debug)]
@@ -17,6 +17,16 @@ pub enum Error {
     #[fail(display = "Error in decoding the seed value: {:?}", error)]
     SeedDecodeError { error: base64::DecodeError },
 
+    /// There was an error in converting an account id from base64 to byte array.
+    #[fail(
+        display = "Error in decoding the account id {}: {:?}",
+        account_id, error
+    )]
+    AccountIdDecodeError {
+        account_id: String,
+        error: base64::DecodeError,
+    },
+
     /// The length of the provided seed was not equal to 32 bytes.
     #[fail(display = "Want seed length 32, got len: {:?}", length)]
     SeedLengthError { length: usize },
@@ -123,6 +133,17 @@ pub enum Error {
     )]
     AccountIdNotFound { account_id: String },
 
+    /// An account with the same id or the same user and ticker already exists on the chain.
+    #[fail(
+        display = "Account {} of user {} for ticker {} already exists on the chain.",
+        account_id, user, ticker
+    )]
+    DuplicateAccount {
+        account_id: String,
+        user: String,
+        ticker: String,
+    },
+
     /// Invalid transaction file
     #[fail(display = "Invalid transaction file: {}.", path)]
     InvalidTransactionFile { path: String },
@@ -152,6 +173,169 @@ pub enum Error {
     )]
     InvalidLastProcessedTxCounter { value: i32 },
 
+    /// The amount is not a valid fixed-point decimal number.
+    #[fail(display = "Invalid amount {}: {}.", amount, reason)]
+    InvalidAmount { amount: String, reason: String },
+
+    /// A transaction was created against an account that reflected a transaction which is
+    /// neither validated nor part of the current batch.
+    #[fail(
+        display = "Transaction {} depends on transaction {}, which is missing from the batch.",
+        tx_id, predecessor
+    )]
+    MissingPredecessor { tx_id: u32, predecessor: u32 },
+
+    /// The sender's and the receiver's accounts of a transfer are for different assets.
+    #[fail(
+        display = "The sender's asset {} does not match the receiver's asset {}.",
+        sender_ticker, receiver_ticker
+    )]
+    AssetIdMismatch {
+        sender_ticker: String,
+        receiver_ticker: String,
+    },
+
+    /// The account is frozen, so no transfer in or out of it can be validated.
+    #[fail(display = "The account {} is frozen.", account_id)]
+    AccountFrozen { account_id: String },
+
+    /// Two validation results could not be merged.
+    #[fail(
+        display = "Failed to merge the validation results of {}-{}: {}.",
+        user, ticker, reason
+    )]
+    ConflictingValidationResults {
+        user: String,
+        ticker: String,
+        reason: String,
+    },
+
+    /// The reconciliation strategy is not one of the supported values.
+    #[fail(
+        display = "Invalid reconciliation strategy: {}. Expected one of skip-failed, halt-on-failure, revert-user.",
+        strategy
+    )]
+    InvalidReconciliationStrategy { strategy: String },
+
+    /// Validation halted because some accounts had failed transactions.
+    #[fail(
+        display = "Validation halted, the following (user, ticker) accounts have failed transactions: {:?}.",
+        accounts
+    )]
+    ReconciliationHalted { accounts: Vec<(String, String)> },
+
+    /// The lock mode is not one of the supported values.
+    #[fail(
+        display = "Invalid lock mode: {}. Expected one of wait, fail-fast.",
+        mode
+    )]
+    InvalidLockMode { mode: String },
+
+    /// Another validator holds the lock on the ledger or on one of its accounts.
+    #[fail(display = "The lock {:?} is held by another validator.", path)]
+    LedgerLocked { path: PathBuf },
+
+    /// The mediator's signing key could not be decoded.
+    #[fail(display = "Invalid signing key of mediator {}: {}.", mediator, reason)]
+    InvalidSigningKey { mediator: String, reason: String },
+
     #[fail(display = "Not implemented, story: {}", story)]
     NotImplemented { story: String },
 }
+
+impl Error {
+    /// Returns true if the error can go away on its own, such as a ledger that is locked by
+    /// another validator, so that the operation is worth retrying. A missing object is not
+    /// transient, since the validator only reads objects that are written before it runs.
+    /// Errors from the library, e.g. invalid proofs, are never transient.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::LedgerLocked { .. } => true,
+            Error::FileReadError { error, .. }
+            | Error::FileCreationError { error, .. }
+            | Error::ObjectSaveError { error, .. }
+            | Error::FileRemovalError { error, .. } => matches!(
+                error.kind(),
+                ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error is caused by another process holding the file that is being
+    /// saved, which is how a locked file shows up on Windows and on networked filesystems.
+    pub fn is_file_contention(&self) -> bool {
+        match self {
+            Error::FileCreationError { error, .. } | Error::ObjectSaveError { error, .. } => {
+                matches!(error.kind(), ErrorKind::WouldBlock)
+                    // Windows denies the access to a file that is open in another process.
+                    || (cfg!(windows) && matches!(error.kind(), ErrorKind::PermissionDenied))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<cryptography::errors::Error> for Error {
+    fn from(error: cryptography::errors::Error) -> Self {
+        Error::LibraryError { error }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptography::asset_id_from_ticker;
+
+    fn library_call(ticker: &str) -> Result<(), Error> {
+        asset_id_from_ticker(ticker)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_library_error_propagates_with_question_mark() {
+        match library_call("TICKER_IS_TOO_LONG") {
+            Err(Error::LibraryError { .. }) => {}
+            result => panic!("Expected a library error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_transient_error_classification() {
+        let io_error = |kind| std::io::Error::new(kind, "test");
+        let path = PathBuf::from("test");
+        assert!(Error::LedgerLocked { path: path.clone() }.is_transient());
+        assert!(Error::FileReadError {
+            error: io_error(ErrorKind::TimedOut),
+            path: path.clone(),
+        }
+        .is_transient());
+        assert!(!Error::FileReadError {
+            error: io_error(ErrorKind::NotFound),
+            path: path.clone(),
+        }
+        .is_transient());
+        assert!(!Error::FileReadError {
+            error: io_error(ErrorKind::PermissionDenied),
+            path,
+        }
+        .is_transient());
+        assert!(!Error::DecodeError.is_transient());
+        assert!(Error::ObjectSaveError {
+            error: io_error(ErrorKind::WouldBlock),
+            path: PathBuf::from("test"),
+        }
+        .is_file_contention());
+        assert_eq!(
+            Error::ObjectSaveError {
+                error: io_error(ErrorKind::PermissionDenied),
+                path: PathBuf::from("test"),
+            }
+            .is_file_contention(),
+            cfg!(windows)
+        );
+        assert!(!library_call("TICKER_IS_TOO_LONG")
+            .unwrap_err()
+            .is_transient());
+    }
+}
@@ -0,0 +1,120 @@
+//! A minimal C ABI for calling the MERCAT transfer verifier from other languages.
+
+use crate::validate::verify_transfer_core;
+use codec::{Decode, Encode};
+use cryptography::mercat::{EncryptedAmount, JustifiedTransferTx, PubAccount};
+use log::error;
+
+/// The transfer is valid.
+pub const MERCAT_OK: i32 = 0;
+/// The input buffer is not a valid SCALE encoded `VerifyTransferRequest`.
+pub const MERCAT_DECODE_ERROR: i32 = 1;
+/// The transfer failed to verify.
+pub const MERCAT_VERIFICATION_ERROR: i32 = 2;
+/// The input pointer is null.
+pub const MERCAT_NULL_POINTER: i32 = 3;
+/// The verifier panicked.
+pub const MERCAT_PANIC: i32 = -1;
+
+/// A justified transfer, along with the on-chain state it is verified against.
+#[derive(Encode, Decode)]
+pub struct VerifyTransferRequest {
+    pub tx: JustifiedTransferTx,
+    pub sender_account: PubAccount,
+    pub sender_pending_balance: EncryptedAmount,
+    pub receiver_account: PubAccount,
+}
+
+/// Verifies a SCALE encoded `VerifyTransferRequest` and returns one of the `MERCAT_*` status
+/// codes. Panics are caught and reported as `MERCAT_PANIC`.
+///
+/// # Safety
+/// `bytes_ptr` must either be null or point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mercat_verify_transfer(bytes_ptr: *const u8, len: usize) -> i32 {
+    if bytes_ptr.is_null() {
+        return MERCAT_NULL_POINTER;
+    }
+    let bytes = std::slice::from_raw_parts(bytes_ptr, len);
+    std::panic::catch_unwind(|| verify_transfer(bytes)).unwrap_or(MERCAT_PANIC)
+}
+
+fn verify_transfer(mut bytes: &[u8]) -> i32 {
+    let request = match VerifyTransferRequest::decode(&mut bytes) {
+        Ok(request) if bytes.is_empty() => request,
+        Ok(_) => {
+            error!("Failed to decode the transfer: trailing bytes");
+            return MERCAT_DECODE_ERROR;
+        }
+        Err(error) => {
+            error!("Failed to decode the transfer: {:?}", error);
+            return MERCAT_DECODE_ERROR;
+        }
+    };
+
+    match verify_transfer_core(
+        &request.tx,
+        &request.sender_account,
+        &request.receiver_account,
+        &request.sender_pending_balance,
+    ) {
+        Ok(_) => MERCAT_OK,
+        Err(error) => {
+            error!("Failed to verify the transfer: {:?}", error);
+            MERCAT_VERIFICATION_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        confidential_transaction_file, load_object, test_chain::TestChain, TransferInstruction,
+        COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+    };
+    use cryptography::mercat::{TransferTxState, TxSubstate};
+
+    fn call_ffi(bytes: &[u8]) -> i32 {
+        unsafe { mercat_verify_transfer(bytes.as_ptr(), bytes.len()) }
+    }
+
+    /// Runs a transfer of 30 ACME from alice to bob through the CLIs' functions, and returns the
+    /// justified transfer along with the state that the validator would check it against.
+    fn justified_transfer() -> VerifyTransferRequest {
+        let (chain, tx_id) = TestChain::with_transfer("ffi");
+        let instruction: TransferInstruction = load_object(
+            chain.db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &confidential_transaction_file(
+                tx_id,
+                &chain.mike,
+                TransferTxState::Justification(TxSubstate::Started),
+            ),
+        )
+        .unwrap();
+        VerifyTransferRequest {
+            tx: JustifiedTransferTx::decode(&mut &instruction.data[..]).unwrap(),
+            sender_account: chain.account(&chain.alice).pub_account,
+            // Alice has no other pending transfers, so her pending balance is her balance.
+            sender_pending_balance: chain.encrypted_balance(&chain.alice),
+            receiver_account: chain.account(&chain.bob).pub_account,
+        }
+    }
+
+    #[test]
+    fn test_verify_transfer_status_codes() {
+        let bytes = justified_transfer().encode();
+        assert_eq!(call_ffi(&bytes), MERCAT_OK);
+        assert_eq!(call_ffi(&bytes[..bytes.len() / 2]), MERCAT_DECODE_ERROR);
+        assert_eq!(
+            call_ffi(&[bytes.clone(), vec![0]].concat()),
+            MERCAT_DECODE_ERROR
+        );
+        assert_eq!(
+            unsafe { mercat_verify_transfer(std::ptr::null(), 0) },
+            MERCAT_NULL_POINTER
+        );
+    }
+}
@@ -6,10 +6,10 @@ use crate::{
     create_rng_from_seed, debug_decrypt_account_balance,
     errors::Error,
     gen_seed, gen_seed_from,
-    justify::{justify_asset_transfer_transaction, process_create_mediator},
+    justify::{justify_asset_transfer_transaction, process_create_mediator, TransferRejection},
     user_public_account_file,
     validate::validate_all_pending,
-    COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+    LockMode, ReconciliationStrategy, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
 };
 use linked_hash_map::LinkedHashMap;
 use log::{error, info, warn};
@@ -354,7 +354,8 @@ impl Transfer {
                 seed.clone(),
                 false, // Do not print the transaction data to stdout.
                 tx_id,
-                reject,
+                // Do not record a rejection reason.
+                reject.then_some(TransferRejection::default()),
                 cheat,
             )?;
             Ok(value.clone())
@@ -491,7 +492,11 @@ impl Validate {
         );
         return Box::new(move || {
             info!("Running: {}", value.clone());
-            validate_all_pending(chain_db_dir.clone())?;
+            validate_all_pending(
+                chain_db_dir.clone(),
+                ReconciliationStrategy::default(),
+                LockMode::default(),
+            )?;
             Ok(value.clone())
         });
     }
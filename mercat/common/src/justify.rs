@@ -1,9 +1,11 @@
+use crate::storage::FileStorage;
 use crate::{
     compute_enc_pending_balance, confidential_transaction_file, construct_path,
-    create_rng_from_seed, errors::Error, last_ordering_state, load_object, non_empty_account_id,
-    save_object, user_public_account_balance_file, user_public_account_file, OrderedPubAccount,
-    OrderedTransferInstruction, TransferInstruction, COMMON_OBJECTS_DIR,
-    MEDIATOR_PUBLIC_ACCOUNT_FILE, OFF_CHAIN_DIR, ON_CHAIN_DIR, SECRET_ACCOUNT_FILE,
+    create_rng_from_seed, errors::Error, last_ordering_state, load_object, mediator_rejection_file,
+    non_empty_account_id, save_object, user_public_account_balance_file, user_public_account_file,
+    MediatorRejection, OrderedPubAccount, OrderedTransferInstruction, TransferInstruction,
+    COMMON_OBJECTS_DIR, MEDIATOR_PUBLIC_ACCOUNT_FILE, MEDIATOR_SIGNING_KEYPAIR_FILE,
+    MEDIATOR_SIGNING_PUBLIC_KEY_FILE, OFF_CHAIN_DIR, ON_CHAIN_DIR, SECRET_ACCOUNT_FILE,
 };
 use base64;
 use codec::{Decode, Encode};
@@ -20,6 +22,7 @@ use curve25519_dalek::scalar::Scalar;
 use log::info;
 use metrics::timing;
 use rand::{CryptoRng, RngCore};
+use schnorrkel::Keypair;
 use std::{path::PathBuf, time::Instant};
 
 fn generate_mediator_keys<R: RngCore + CryptoRng>(
@@ -39,6 +42,19 @@ fn generate_mediator_keys<R: RngCore + CryptoRng>(
     )
 }
 
+pub(crate) fn load_signing_keypair(db_dir: PathBuf, mediator: &str) -> Result<Keypair, Error> {
+    let bytes: Vec<u8> = load_object(
+        db_dir,
+        OFF_CHAIN_DIR,
+        mediator,
+        MEDIATOR_SIGNING_KEYPAIR_FILE,
+    )?;
+    Keypair::from_bytes(&bytes).map_err(|error| Error::InvalidSigningKey {
+        mediator: mediator.to_string(),
+        reason: format!("{:?}", error),
+    })
+}
+
 pub fn process_create_mediator(seed: String, db_dir: PathBuf, user: String) -> Result<(), Error> {
     // Setup the rng.
     let mut rng = create_rng_from_seed(Some(seed))?;
@@ -46,6 +62,7 @@ pub fn process_create_mediator(seed: String, db_dir: PathBuf, user: String) -> R
     // Generate keys for the mediator.
     let mediator_key_gen_timer = Instant::now();
     let (public_account, private_account) = generate_mediator_keys(&mut rng);
+    let signing_keypair = Keypair::generate_with(&mut rng);
     timing!(
         "mediator.key_gen",
         mediator_key_gen_timer,
@@ -63,12 +80,28 @@ pub fn process_create_mediator(seed: String, db_dir: PathBuf, user: String) -> R
     )?;
 
     save_object(
-        db_dir,
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &user,
+        MEDIATOR_SIGNING_PUBLIC_KEY_FILE,
+        &signing_keypair.public.to_bytes().to_vec(),
+    )?;
+
+    save_object(
+        db_dir.clone(),
         OFF_CHAIN_DIR,
         &user,
         SECRET_ACCOUNT_FILE,
         &private_account,
     )?;
+
+    save_object(
+        db_dir,
+        OFF_CHAIN_DIR,
+        &user,
+        MEDIATOR_SIGNING_KEYPAIR_FILE,
+        &signing_keypair.to_bytes().to_vec(),
+    )?;
     info!(
         "CLI log: Mediator keys as base64:\n{}\n",
         base64::encode(public_account.encode())
@@ -83,6 +116,13 @@ pub fn process_create_mediator(seed: String, db_dir: PathBuf, user: String) -> R
     Ok(())
 }
 
+/// The mediator's decision to reject a transfer instead of justifying it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TransferRejection {
+    /// An application defined code for the reason of the rejection.
+    pub reason_code: Option<u32>,
+}
+
 pub fn justify_asset_transfer_transaction(
     db_dir: PathBuf,
     sender: String,
@@ -92,7 +132,7 @@ pub fn justify_asset_transfer_transaction(
     seed: String,
     stdout: bool,
     tx_id: u32,
-    reject: bool,
+    rejection: Option<TransferRejection>,
     cheat: bool,
 ) -> Result<(), Error> {
     // Load the transaction, mediator's credentials, and issuer's public account.
@@ -167,7 +207,7 @@ pub fn justify_asset_transfer_transaction(
         sender.clone(),
         last_processed_tx_counter,
         tx_id,
-        db_dir.clone(),
+        &FileStorage::new(db_dir.clone()),
     )?;
 
     let pending_balance = compute_enc_pending_balance(
@@ -175,22 +215,21 @@ pub fn justify_asset_transfer_transaction(
         ordering_state,
         last_processed_tx_counter,
         last_processed_account_balance,
+        &FileStorage::new(db_dir.clone()),
         db_dir.clone(),
     )?;
 
-    let asset_id = asset_id_from_ticker(&ticker).map_err(|error| Error::LibraryError { error })?;
-    let mut justified_tx = CtxMediator
-        .justify_transaction(
-            asset_tx.clone(),
-            &mediator_account.encryption_key,
-            &sender_ordered_pub_account.pub_account,
-            &pending_balance,
-            &receiver_ordered_pub_account.pub_account,
-            &[],
-            asset_id,
-            &mut rng,
-        )
-        .map_err(|error| Error::LibraryError { error })?;
+    let asset_id = asset_id_from_ticker(&ticker)?;
+    let mut justified_tx = CtxMediator.justify_transaction(
+        asset_tx.clone(),
+        &mediator_account.encryption_key,
+        &sender_ordered_pub_account.pub_account,
+        &pending_balance,
+        &receiver_ordered_pub_account.pub_account,
+        &[],
+        asset_id,
+        &mut rng,
+    )?;
 
     if cheat {
         info!(
@@ -210,8 +249,8 @@ pub fn justify_asset_transfer_transaction(
 
     let next_instruction;
     let justify_save_objects_timer = Instant::now();
-    // If the `reject` flag is set, save the transaction as rejected.
-    if reject {
+    // If the transfer is rejected, save the transaction as rejected.
+    if let Some(rejection) = rejection {
         let rejected_state = TransferTxState::Justification(TxSubstate::Rejected);
         next_instruction = TransferInstruction {
             data: asset_tx.encode().to_vec(),
@@ -225,6 +264,18 @@ pub fn justify_asset_transfer_transaction(
             &confidential_transaction_file(tx_id, &sender, rejected_state),
             &next_instruction,
         )?;
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &mediator_rejection_file(tx_id),
+            &MediatorRejection::new(
+                tx_id,
+                mediator.clone(),
+                rejection.reason_code,
+                &load_signing_keypair(db_dir.clone(), &mediator)?,
+            ),
+        )?;
         if stdout {
             info!(
                 "CLI log: tx-{}: Transaction as base64:\n{}\n",
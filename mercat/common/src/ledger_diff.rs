@@ -0,0 +1,183 @@
+//! Compares the on-chain account balances of two copies of the database directory.
+
+use crate::{
+    debug_decrypt_account_balance, errors::Error, load_object_from, read_dir_paths,
+    COMMON_OBJECTS_DIR, ON_CHAIN_DIR, VALIDATED_PUBLIC_ACCOUNT_BALANCE_FILE,
+};
+use codec::Encode;
+use cryptography::mercat::EncryptedAmount;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// How the balance of an account differs between two ledgers.
+#[derive(Debug, PartialEq)]
+pub enum BalanceChange {
+    /// The account only exists in the first ledger.
+    OnlyInA,
+    /// The account only exists in the second ledger.
+    OnlyInB,
+    /// The encrypted balance is different. The plaintext delta, `b - a`, is only available
+    /// when the account's secret key can be found in the off-chain directory of both ledgers.
+    /// Otherwise, the error of the decryption is reported in its place.
+    Changed { delta: Result<i64, String> },
+}
+
+/// An account whose balance differs between two ledgers.
+#[derive(Debug)]
+pub struct AccountDiff {
+    pub user: String,
+    pub ticker: String,
+    pub change: BalanceChange,
+}
+
+/// Reports every account, present in either of the ledgers, whose encrypted balance
+/// differs between the two. The result is sorted by user and ticker.
+pub fn diff_ledgers(dir_a: PathBuf, dir_b: PathBuf) -> Result<Vec<AccountDiff>, Error> {
+    let mut balances_a = load_balances(dir_a.clone())?;
+    let balances_b = load_balances(dir_b.clone())?;
+
+    let mut diffs = vec![];
+    for ((user, ticker), balance_b) in balances_b {
+        let change = match balances_a.remove(&(user.clone(), ticker.clone())) {
+            None => BalanceChange::OnlyInB,
+            Some(balance_a) if balance_a.encode() == balance_b.encode() => continue,
+            Some(_) => BalanceChange::Changed {
+                delta: plaintext_delta(&user, &ticker, dir_a.clone(), dir_b.clone()),
+            },
+        };
+        diffs.push(AccountDiff {
+            user,
+            ticker,
+            change,
+        });
+    }
+    for (user, ticker) in balances_a.into_keys() {
+        diffs.push(AccountDiff {
+            user,
+            ticker,
+            change: BalanceChange::OnlyInA,
+        });
+    }
+    diffs.sort_by(|a, b| (&a.user, &a.ticker).cmp(&(&b.user, &b.ticker)));
+    Ok(diffs)
+}
+
+/// Loads the validated balance of every account in the ledger, keyed by (user, ticker).
+fn load_balances(db_dir: PathBuf) -> Result<BTreeMap<(String, String), EncryptedAmount>, Error> {
+    let balance_suffix = format!("_{}", VALIDATED_PUBLIC_ACCOUNT_BALANCE_FILE);
+    let mut dir = db_dir;
+    dir.push(ON_CHAIN_DIR);
+
+    let mut balances = BTreeMap::new();
+    for user_dir in read_dir_paths(dir)? {
+        let user = match user_dir.file_name().and_then(|name| name.to_str()) {
+            Some(user) if user_dir.is_dir() && user != COMMON_OBJECTS_DIR => user.to_string(),
+            _ => continue,
+        };
+        for file in read_dir_paths(user_dir.clone())? {
+            let file_name = file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(Error::PathBufConversionError)?;
+            if file_name.ends_with(&balance_suffix) {
+                let ticker = &file_name[..file_name.len() - balance_suffix.len()];
+                let balance: EncryptedAmount = load_object_from(file.clone())?;
+                balances.insert((user.clone(), ticker.to_string()), balance);
+            }
+        }
+    }
+    Ok(balances)
+}
+
+fn plaintext_delta(
+    user: &str,
+    ticker: &str,
+    dir_a: PathBuf,
+    dir_b: PathBuf,
+) -> Result<i64, String> {
+    let decrypt = |db_dir| {
+        debug_decrypt_account_balance(user.to_string(), ticker.to_string(), db_dir)
+            .map_err(|error| error.to_string())
+    };
+    let balance_a = decrypt(dir_a)?;
+    let balance_b = decrypt(dir_b)?;
+    Ok(balance_b as i64 - balance_a as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{save_object, test_chain::TestChain, user_public_account_balance_file};
+    use cryptography::asset_proofs::CipherText;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+    use curve25519_dalek::scalar::Scalar;
+
+    fn save_balance(db_dir: PathBuf, user: &str, ticker: &str, value: u64) {
+        let point = RISTRETTO_BASEPOINT_POINT * Scalar::from(value);
+        let balance = CipherText { x: point, y: point };
+        save_object(
+            db_dir,
+            ON_CHAIN_DIR,
+            user,
+            &user_public_account_balance_file(&ticker.to_string()),
+            &balance,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_diff_ledgers_reports_only_the_changed_account() {
+        let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        root.push("chain_dir/unittest/ledger_diff");
+        let _ = std::fs::remove_dir_all(root.clone());
+        let (dir_a, dir_b) = (root.join("a"), root.join("b"));
+
+        for db_dir in &[dir_a.clone(), dir_b.clone()] {
+            save_balance(db_dir.clone(), "alice", "ACME", 10);
+            save_balance(db_dir.clone(), "bob", "ACME", 20);
+        }
+        save_balance(dir_b.clone(), "bob", "ACME", 25);
+
+        let diffs = diff_ledgers(dir_a, dir_b).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].user, "bob");
+        assert_eq!(diffs[0].ticker, "ACME");
+        // No secret keys are available, so the delta is unknown.
+        assert!(matches!(
+            diffs[0].change,
+            BalanceChange::Changed { delta: Err(_) }
+        ));
+    }
+
+    #[test]
+    fn test_diff_ledgers_reports_the_accounts_of_only_one_ledger() {
+        let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        root.push("chain_dir/unittest/ledger_diff_only_in_one");
+        let _ = std::fs::remove_dir_all(root.clone());
+        let (dir_a, dir_b) = (root.join("a"), root.join("b"));
+
+        save_balance(dir_a.clone(), "alice", "ACME", 10);
+        save_balance(dir_b.clone(), "bob", "ACME", 10);
+
+        let diffs = diff_ledgers(dir_a, dir_b).unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].user, "alice");
+        assert_eq!(diffs[0].change, BalanceChange::OnlyInA);
+        assert_eq!(diffs[1].user, "bob");
+        assert_eq!(diffs[1].change, BalanceChange::OnlyInB);
+    }
+
+    #[test]
+    fn test_diff_ledgers_reports_the_plaintext_delta() {
+        // Alice holds 50 in both ledgers, and sends 30 to bob in the second one.
+        let chain_a = TestChain::funded("ledger_diff_delta_a");
+        let (chain_b, _) = TestChain::with_transfer("ledger_diff_delta_b");
+        chain_b.validate();
+
+        let diffs = diff_ledgers(chain_a.db_dir.clone(), chain_b.db_dir.clone()).unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].user, chain_a.alice);
+        assert_eq!(diffs[0].change, BalanceChange::Changed { delta: Ok(-30) });
+        assert_eq!(diffs[1].user, chain_a.bob);
+        assert_eq!(diffs[1].change, BalanceChange::Changed { delta: Ok(30) });
+    }
+}
@@ -5,8 +5,11 @@ pub mod account_issue;
 pub mod account_transfer;
 pub mod chain_setup;
 pub mod errors;
+pub mod ffi;
 mod harness;
 pub mod justify;
+pub mod ledger_diff;
+pub mod storage;
 pub mod validate;
 
 use base64;
@@ -14,29 +17,35 @@ use codec::{Decode, Encode};
 use cryptography::{
     asset_proofs::CipherText,
     mercat::{
-        Account, AssetTxState, EncryptedAmount, EncryptedAssetId, FinalizedTransferTx,
-        InitializedAssetTx, InitializedTransferTx, JustifiedTransferTx, PubAccount, PubAccountTx,
-        SecAccount, TransferTxState, TxSubstate,
+        Account, AssetTxState, EncryptedAmount, EncryptedAssetId, EncryptionPubKey,
+        FinalizedTransferTx, InitializedAssetTx, InitializedTransferTx, JustifiedTransferTx,
+        PubAccount, PubAccountTx, SecAccount, TransferTxState, TxSubstate,
     },
 };
 use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, scalar::Scalar};
 use errors::Error;
+use fs2::FileExt;
 use log::{debug, error, info};
 use metrics::Recorder;
 use metrics_core::Key;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use rand::{CryptoRng, RngCore};
 use regex::Regex;
+use schnorrkel::{Keypair, PublicKey, Signature};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::TryInto,
     fmt,
-    fs::{create_dir_all, File},
+    fs::{create_dir_all, File, OpenOptions},
     hash::Hash,
-    io::BufReader,
+    io::{BufReader, ErrorKind},
     path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Duration,
 };
+use storage::{FileStorage, Storage};
 
 pub const ON_CHAIN_DIR: &str = "on-chain";
 pub const OFF_CHAIN_DIR: &str = "off-chain";
@@ -48,11 +57,25 @@ pub const ASSET_ID_LIST_FILE: &str = "valid_asset_ids.json";
 pub const COMMON_OBJECTS_DIR: &str = "common";
 pub const USER_ACCOUNT_MAP: &str = "user_ticker_to_account_id.json";
 pub const LAST_VALIDATED_TX_ID_FILE: &str = "last_validated_tx_id_file.json";
+pub const MEDIATOR_REJECTION_FILE: &str = "mediator_rejection";
+pub const MEDIATOR_SIGNING_PUBLIC_KEY_FILE: &str = "mediator_signing_public_key";
+pub const MEDIATOR_SIGNING_KEYPAIR_FILE: &str = "mediator_signing_keypair";
+/// The signing context of the mediator rejections.
+pub const MEDIATOR_REJECTION_SIGNING_CONTEXT: &[u8] = b"mercat-mediator-rejection";
+pub const VALIDATION_CHECKPOINT_FILE: &str = "validation_checkpoint.json";
+pub const FROZEN_ACCOUNT_FILE: &str = "frozen";
+#[cfg(feature = "audit")]
+pub const AUDIT_LOG_FILE: &str = "audit_log";
+
+/// Written to the logs in place of a value that could not be decrypted.
+const OUT_OF_RANGE_PLACEHOLDER: &str = "<out of range>";
+/// Written to the logs in place of a value whose account could not be loaded.
+const ACCOUNT_NOT_FOUND_PLACEHOLDER: &str = "<account not found>";
 
 /// A wrapper around MERCAT api which holds the transaction data, the transaction id,
 /// and the user who initiated the transaction. Some transactions also hold the
 /// ordering state.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum CoreTransaction {
     Account {
         account_tx: PubAccountTx,
@@ -124,6 +147,64 @@ impl CoreTransaction {
         }
     }
 
+    /// Returns true if the sender or the receiver of a pending transfer is the given encoded
+    /// account id.
+    fn involves_account(&self, account_id: &[u8]) -> bool {
+        let memo = match self {
+            CoreTransaction::TransferInit { tx, .. } => &tx.memo,
+            CoreTransaction::TransferFinalize { tx, .. } => &tx.init_data.memo,
+            _ => return false,
+        };
+        memo.sender_account_id.encode() == account_id
+            || memo.receiver_account_id.encode() == account_id
+    }
+
+    /// Returns the id of the transaction, or None for an invalid transaction.
+    pub fn tx_id(&self) -> Option<u32> {
+        match self {
+            CoreTransaction::Account { tx_id, .. } => Some(*tx_id),
+            CoreTransaction::IssueInit { tx_id, .. } => Some(*tx_id),
+            CoreTransaction::TransferInit { tx_id, .. } => Some(*tx_id),
+            CoreTransaction::TransferFinalize { tx_id, .. } => Some(*tx_id),
+            CoreTransaction::TransferJustify { tx_id, .. } => Some(*tx_id),
+            CoreTransaction::Invalid => None,
+        }
+    }
+
+    /// Returns the encryption public keys of all the parties that the transaction involves. For
+    /// transfers these are the sender's and the receiver's keys, followed by the mediator's key
+    /// once the transfer is justified. Except for the account creation transaction, which
+    /// carries its own key, the keys are read from the validated accounts in `storage`.
+    pub fn referenced_keys(&self, storage: &dyn Storage) -> Result<Vec<EncryptionPubKey>, Error> {
+        let account_key = |account_id: EncryptedAssetId| -> Result<EncryptionPubKey, Error> {
+            let (user, ticker, _) = get_user_ticker_from(account_id, storage)?;
+            let account: OrderedPubAccount =
+                storage.get(ON_CHAIN_DIR, &user, &user_public_account_file(&ticker))?;
+            Ok(account.pub_account.owner_enc_pub_key)
+        };
+        let memo = match self {
+            CoreTransaction::Account { account_tx, .. } => {
+                return Ok(vec![account_tx.pub_account.owner_enc_pub_key.clone()]);
+            }
+            CoreTransaction::IssueInit { issue_tx, .. } => {
+                return Ok(vec![account_key(issue_tx.account_id)?]);
+            }
+            CoreTransaction::TransferInit { tx, .. } => &tx.memo,
+            CoreTransaction::TransferFinalize { tx, .. } => &tx.init_data.memo,
+            CoreTransaction::TransferJustify { tx, .. } => &tx.finalized_data.init_data.memo,
+            CoreTransaction::Invalid => return Ok(vec![]),
+        };
+
+        let mut keys = vec![
+            account_key(memo.sender_account_id)?,
+            account_key(memo.receiver_account_id)?,
+        ];
+        if let CoreTransaction::TransferJustify { mediator, .. } = self {
+            keys.push(storage.get(ON_CHAIN_DIR, mediator, MEDIATOR_PUBLIC_ACCOUNT_FILE)?);
+        }
+        Ok(keys)
+    }
+
     pub fn ordering_state(&self) -> OrderingState {
         match self {
             CoreTransaction::Account {
@@ -155,33 +236,295 @@ impl CoreTransaction {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
 pub enum Direction {
     Incoming,
     Outgoing,
 }
 
+/// The reason a transaction failed to validate.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationFailure {
+    /// The user or ticker of an account could not be found in the account map.
+    UserLookupFailed,
+    /// The transaction references an account id that is not in the account map.
+    AccountNotFound { account_id: String },
+    /// The sender's or the receiver's account is frozen.
+    AccountFrozen { account_id: String },
+    /// The freeze flag of the sender's or the receiver's account could not be read.
+    FreezeFlagUnreadable { error: String },
+    /// The sender's and the receiver's accounts are for different assets.
+    AssetIdMismatch {
+        sender_ticker: String,
+        receiver_ticker: String,
+    },
+    /// An object, such as an account or the transaction itself, could not be loaded.
+    MissingObject,
+    /// The library rejected the transaction's proofs.
+    ProofInvalid,
+    /// The validated transaction could not be saved.
+    SaveFailed,
+    /// The reason was not recorded.
+    Unknown,
+}
+
+/// An encrypted account balance. Only transfer amounts can be applied to it, which rules out
+/// adding two balances together.
+#[derive(Clone, Debug)]
+pub struct EncryptedBalance(EncryptedAmount);
+
+impl EncryptedBalance {
+    /// Adds an incoming transfer amount to the balance.
+    pub fn apply_incoming(&mut self, amount: &EncryptedTransferAmount) {
+        self.0 += amount.0.clone();
+    }
+
+    /// Subtracts an outgoing transfer amount from the balance.
+    pub fn apply_outgoing(&mut self, amount: &EncryptedTransferAmount) {
+        self.0 -= amount.0.clone();
+    }
+}
+
+impl From<EncryptedAmount> for EncryptedBalance {
+    fn from(balance: EncryptedAmount) -> Self {
+        Self(balance)
+    }
+}
+
+impl From<EncryptedBalance> for EncryptedAmount {
+    fn from(balance: EncryptedBalance) -> Self {
+        balance.0
+    }
+}
+
+/// An encrypted amount that is moved in or out of an account, e.g. a transfer or an issuance.
+#[derive(Clone, Debug)]
+pub struct EncryptedTransferAmount(EncryptedAmount);
+
+impl From<EncryptedAmount> for EncryptedTransferAmount {
+    fn from(amount: EncryptedAmount) -> Self {
+        Self(amount)
+    }
+}
+
+impl From<EncryptedTransferAmount> for EncryptedAmount {
+    fn from(amount: EncryptedTransferAmount) -> Self {
+        amount.0
+    }
+}
+
 /// A wrapper that hides the validation error and only keeps the result of the validation.
 #[derive(Clone)]
 pub struct ValidationResult {
     user: String,
     ticker: String,
     direction: Direction,
-    amount: Option<EncryptedAmount>,
+    amount: Option<EncryptedTransferAmount>,
+    failure: Option<ValidationFailure>,
+    /// The amount is already reflected in the account's balance, since the account was saved by
+    /// an interrupted run of the same batch.
+    persisted: bool,
 }
 
 impl ValidationResult {
     /// Creates the error value. An amount of None, indicates that an error has occurred.
     fn error(user: &str, ticker: &str) -> Self {
+        Self::failed(user, ticker, ValidationFailure::Unknown)
+    }
+
+    /// Creates the error value, recording the reason of the failure.
+    fn failed(user: &str, ticker: &str, failure: ValidationFailure) -> Self {
         Self {
             user: user.to_string(),
             ticker: ticker.to_string(),
             direction: Direction::Incoming,
             amount: None,
+            failure: Some(failure),
+            persisted: false,
+        }
+    }
+
+    /// Returns the reason of the failure, if the validation has failed.
+    pub fn failure(&self) -> Option<&ValidationFailure> {
+        self.failure.as_ref()
+    }
+
+    /// Combines two results of the same account and direction into one, whose amount is the
+    /// sum of the two amounts, so that it can be applied to the balance with a single
+    /// homomorphic operation. If either result has failed, the combined result is failed too.
+    pub fn merge(self, other: ValidationResult) -> Result<ValidationResult, Error> {
+        let conflict = |reason: &str| Error::ConflictingValidationResults {
+            user: self.user.clone(),
+            ticker: self.ticker.clone(),
+            reason: reason.to_string(),
+        };
+        if (&self.user, &self.ticker) != (&other.user, &other.ticker) {
+            return Err(conflict("the results belong to different accounts"));
+        }
+        if self.persisted != other.persisted {
+            return Err(conflict("only one of the results is already persisted"));
+        }
+        if let Some(failure) = self.failure.clone().or(other.failure) {
+            return Ok(Self::failed(&self.user, &self.ticker, failure));
+        }
+        if self.direction != other.direction {
+            return Err(conflict("the results have opposite directions"));
+        }
+        let amount = match (self.amount, other.amount) {
+            (Some(EncryptedTransferAmount(mut amount)), Some(other_amount)) => {
+                amount += other_amount.0;
+                Some(EncryptedTransferAmount(amount))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            amount,
+            failure: None,
+            ..self
+        })
+    }
+}
+
+/// Decides how the validator updates the balances of the accounts that have at least one failed
+/// validation result, i.e. a result with no amount, in the current batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ReconciliationStrategy {
+    /// Ignore the failed results and apply the rest.
+    #[default]
+    SkipFailed,
+    /// Leave every balance untouched and return an error if any account has a failed result.
+    HaltOnFailure,
+    /// Leave all the accounts of a user with a failed result untouched, and update the rest.
+    RevertUser,
+}
+
+impl std::str::FromStr for ReconciliationStrategy {
+    type Err = Error;
+
+    fn from_str(strategy: &str) -> Result<Self, Error> {
+        match strategy {
+            "skip-failed" => Ok(ReconciliationStrategy::SkipFailed),
+            "halt-on-failure" => Ok(ReconciliationStrategy::HaltOnFailure),
+            "revert-user" => Ok(ReconciliationStrategy::RevertUser),
+            _ => Err(Error::InvalidReconciliationStrategy {
+                strategy: strategy.to_string(),
+            }),
+        }
+    }
+}
+
+/// Decides what a validator does when another validator holds a lock that it needs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum LockMode {
+    /// Block until the lock is released.
+    #[default]
+    Wait,
+    /// Return `LedgerLocked` right away.
+    FailFast,
+}
+
+impl std::str::FromStr for LockMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self, Error> {
+        match mode {
+            "wait" => Ok(LockMode::Wait),
+            "fail-fast" => Ok(LockMode::FailFast),
+            _ => Err(Error::InvalidLockMode {
+                mode: mode.to_string(),
+            }),
         }
     }
 }
 
+/// The options of a validation run.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ValidationOptions {
+    pub strategy: ReconciliationStrategy,
+    pub lock_mode: LockMode,
+    /// Skip the transactions that fail to load or to validate, instead of failing the batch.
+    pub partial: bool,
+    /// Write nothing to the on-chain directory.
+    pub dry_run: bool,
+    /// Save the validated instructions again, even if they are already on file.
+    pub force_rewrite: bool,
+}
+
+/// The on-chain record of a mediator declining to justify a transfer.
+#[derive(Debug, Serialize, Deserialize, Encode, Decode, Clone)]
+pub struct MediatorRejection {
+    pub tx_id: u32,
+    pub mediator: String,
+    /// An application defined code for the reason of the rejection.
+    pub reason_code: Option<u32>,
+    /// The mediator's signature of the other fields.
+    pub signature: Vec<u8>,
+}
+
+impl MediatorRejection {
+    /// Creates a rejection of the transfer `tx_id`, signed with the mediator's signing key.
+    pub fn new(tx_id: u32, mediator: String, reason_code: Option<u32>, keypair: &Keypair) -> Self {
+        let mut rejection = MediatorRejection {
+            tx_id,
+            mediator,
+            reason_code,
+            signature: vec![],
+        };
+        rejection.signature = keypair
+            .sign_simple(
+                MEDIATOR_REJECTION_SIGNING_CONTEXT,
+                &rejection.signed_message(),
+            )
+            .to_bytes()
+            .to_vec();
+        rejection
+    }
+
+    /// Returns true if the rejection is signed by the owner of `public_key`.
+    pub fn is_signed_by(&self, public_key: &PublicKey) -> bool {
+        Signature::from_bytes(&self.signature)
+            .and_then(|signature| {
+                public_key.verify_simple(
+                    MEDIATOR_REJECTION_SIGNING_CONTEXT,
+                    &self.signed_message(),
+                    &signature,
+                )
+            })
+            .is_ok()
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        (self.tx_id, &self.mediator, self.reason_code).encode()
+    }
+}
+
+/// Records the accounts whose new balance has already been saved while applying a batch of
+/// validation results. Lets the validator resume an interrupted batch without applying the
+/// same results to an account twice.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct ValidationCheckpoint {
+    /// The last tx_id of the batch that is being applied.
+    pub last_tx_id: Option<u32>,
+    /// The (user, ticker) accounts that are already saved.
+    pub saved_accounts: Vec<(String, String)>,
+}
+
+/// An entry of the audit log, which records an update of an account balance by the validator.
+#[cfg(feature = "audit")]
+#[derive(Debug, Encode, Decode, Clone, PartialEq)]
+pub struct BalanceMutation {
+    pub user: String,
+    pub ticker: String,
+    /// The tx_ids and directions of the amounts that were applied to the balance.
+    pub applied: Vec<(u32, Direction)>,
+    /// The decrypted balances before and after the update, or None if they could not be
+    /// decrypted.
+    pub old_balance: Option<u32>,
+    pub new_balance: Option<u32>,
+    /// The time of the update, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
 /// Used in processing of pending transactions.
 #[derive(Debug, Serialize, Deserialize, Encode, Decode, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -292,6 +635,16 @@ pub fn user_public_account_balance_file(ticker: &String) -> String {
     format!("{}_{}", ticker, VALIDATED_PUBLIC_ACCOUNT_BALANCE_FILE)
 }
 
+#[inline]
+pub fn user_frozen_account_file(ticker: &String) -> String {
+    format!("{}_{}", ticker, FROZEN_ACCOUNT_FILE)
+}
+
+#[inline]
+pub fn mediator_rejection_file(tx_id: u32) -> String {
+    format!("{}_{}", MEDIATOR_REJECTION_FILE, tx_id)
+}
+
 #[inline]
 pub fn user_secret_account_file(ticker: &String) -> String {
     format!("{}_{}", ticker, SECRET_ACCOUNT_FILE)
@@ -387,6 +740,33 @@ pub fn construct_path(db_dir: PathBuf, on_off_chain: &str, user: &str, file_name
     file_path
 }
 
+/// The number of times `save_to_file` and the saves of a `FileStorage` are retried, when they
+/// fail because the file is held by another process.
+pub const DEFAULT_SAVE_RETRIES: u32 = 3;
+
+/// The delay before the first retry of a save. It is doubled for each of the following retries.
+const SAVE_RETRY_BACKOFF: Duration = Duration::from_millis(20);
+
+/// Calls `save` until it succeeds, fails with an error other than a file contention, or has been
+/// retried `retries` times.
+pub(crate) fn retry_on_contention(
+    retries: u32,
+    mut save: impl FnMut() -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut backoff = SAVE_RETRY_BACKOFF;
+    for _ in 0..retries {
+        match save() {
+            Err(error) if error.is_file_contention() => {
+                debug!("Retrying the save in {:?}: {:?}", backoff, error);
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    save()
+}
+
 /// Utility function to save a serializable data to a location inside the database directory,
 /// for a particular user.
 #[inline]
@@ -397,6 +777,21 @@ pub fn save_to_file<T>(
     file_name: &str,
     data: &T,
 ) -> Result<(), Error>
+where
+    T: ?Sized + serde::Serialize,
+{
+    retry_on_contention(DEFAULT_SAVE_RETRIES, || {
+        save_to_file_once(db_dir.clone(), on_off_chain, user, file_name, data)
+    })
+}
+
+fn save_to_file_once<T>(
+    db_dir: PathBuf,
+    on_off_chain: &str,
+    user: &str,
+    file_name: &str,
+    data: &T,
+) -> Result<(), Error>
 where
     T: ?Sized + serde::Serialize,
 {
@@ -446,6 +841,121 @@ pub fn load_from_file<T: serde::de::DeserializeOwned>(
     })
 }
 
+/// Same as `save_to_file`, but the data is first written to a temporary file which is then
+/// renamed to `file_name`. Readers either see the old or the new content, never a partial write.
+pub fn save_to_file_atomically<T>(
+    db_dir: PathBuf,
+    on_off_chain: &str,
+    user: &str,
+    file_name: &str,
+    data: &T,
+) -> Result<(), Error>
+where
+    T: ?Sized + serde::Serialize,
+{
+    let tmp_file_name = format!("{}.tmp", file_name);
+    save_to_file(db_dir.clone(), on_off_chain, user, &tmp_file_name, data)?;
+
+    let tmp_path = construct_path(db_dir.clone(), on_off_chain, user, &tmp_file_name);
+    let file_path = construct_path(db_dir, on_off_chain, user, file_name);
+    std::fs::rename(tmp_path, file_path.clone()).map_err(|error| Error::ObjectSaveError {
+        error,
+        path: file_path,
+    })
+}
+
+/// Appends an entry to the `AUDIT_LOG_FILE`. The entries are SCALE encoded one after the other,
+/// and the existing entries are never rewritten.
+#[cfg(feature = "audit")]
+pub fn append_to_audit_log(db_dir: PathBuf, mutation: &BalanceMutation) -> Result<(), Error> {
+    use std::io::Write;
+
+    let mut file_path = db_dir;
+    file_path.push(OFF_CHAIN_DIR);
+    file_path.push(COMMON_OBJECTS_DIR);
+    create_dir_all(file_path.clone()).map_err(|error| Error::FileCreationError {
+        error,
+        path: file_path.clone(),
+    })?;
+    file_path.push(AUDIT_LOG_FILE);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path.clone())
+        .map_err(|error| Error::FileCreationError {
+            error,
+            path: file_path.clone(),
+        })?;
+    file.write_all(&mutation.encode())
+        .map_err(|error| Error::ObjectSaveError {
+            error,
+            path: file_path,
+        })
+}
+
+/// Reads all the entries of the `AUDIT_LOG_FILE`, oldest first.
+#[cfg(feature = "audit")]
+pub fn load_audit_log(db_dir: PathBuf) -> Result<Vec<BalanceMutation>, Error> {
+    let file_path = construct_path(db_dir, OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, AUDIT_LOG_FILE);
+    let data = std::fs::read(file_path.clone()).map_err(|error| Error::FileReadError {
+        error,
+        path: file_path.clone(),
+    })?;
+    let mut input = &data[..];
+    let mut entries = vec![];
+    while !input.is_empty() {
+        entries.push(BalanceMutation::decode(&mut input).map_err(|error| {
+            Error::ObjectLoadError {
+                error,
+                path: file_path.clone(),
+            }
+        })?);
+    }
+    Ok(entries)
+}
+
+/// Takes an exclusive advisory lock on `file_name` by locking `<file_name>.lock` next to it.
+/// The lock is released when the returned file is dropped.
+pub fn lock_file(
+    db_dir: PathBuf,
+    on_off_chain: &str,
+    user: &str,
+    file_name: &str,
+    mode: LockMode,
+) -> Result<File, Error> {
+    let mut lock_path = db_dir;
+    lock_path.push(on_off_chain);
+    lock_path.push(user);
+    create_dir_all(lock_path.clone()).map_err(|error| Error::FileCreationError {
+        error,
+        path: lock_path.clone(),
+    })?;
+
+    lock_path.push(format!("{}.lock", file_name));
+    let lock = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path.clone())
+        .map_err(|error| Error::FileCreationError {
+            error,
+            path: lock_path.clone(),
+        })?;
+    match mode {
+        LockMode::Wait => lock
+            .lock_exclusive()
+            .map_err(|error| Error::FileCreationError {
+                error,
+                path: lock_path,
+            })?,
+        LockMode::FailFast => lock
+            .try_lock_exclusive()
+            .map_err(|_| Error::LedgerLocked { path: lock_path })?,
+    }
+    Ok(lock)
+}
+
 /// Utility function to remove a file from the database directory for a particular user.
 #[inline]
 pub fn remove_file(
@@ -466,19 +976,16 @@ pub fn remove_file(
 #[derive(Serialize, Deserialize)]
 pub struct AssetIdList(pub Vec<Scalar>);
 
-/// Utility function to read the asset ids from the database directory.
+/// Utility function to read the asset ids from the storage.
 #[inline]
-pub fn get_asset_ids(db_dir: PathBuf) -> Result<Vec<Scalar>, Error> {
-    let file_path = construct_path(db_dir, ON_CHAIN_DIR, COMMON_OBJECTS_DIR, ASSET_ID_LIST_FILE);
-    let file = File::open(file_path).map_err(|error| Error::FileReadError {
-        error,
-        path: ASSET_ID_LIST_FILE.into(),
-    })?;
-    let mut de = serde_json::Deserializer::from_reader(file);
-
-    let valid_asset_ids =
-        AssetIdList::deserialize(&mut de).map_err(|_| Error::AssetIdListDeserializeError {
-            path: ASSET_ID_LIST_FILE.into(),
+pub fn get_asset_ids(storage: &dyn Storage) -> Result<Vec<Scalar>, Error> {
+    let valid_asset_ids: AssetIdList = storage
+        .get_json(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, ASSET_ID_LIST_FILE)
+        .map_err(|error| match error {
+            Error::ObjectDeserializationError { .. } => Error::AssetIdListDeserializeError {
+                path: ASSET_ID_LIST_FILE.into(),
+            },
+            error => error,
         })?;
     Ok(valid_asset_ids.0)
 }
@@ -492,24 +999,7 @@ pub fn save_object<T: Encode>(
     file_name: &str,
     data: &T,
 ) -> Result<(), Error> {
-    let mut file_path = db_dir;
-    file_path.push(on_off_chain);
-    file_path.push(user);
-
-    // The file_path is now the path to the user directory. Create it if it does not exist.
-    create_dir_all(file_path.clone()).map_err(|error| Error::FileCreationError {
-        error,
-        path: file_path.clone(),
-    })?;
-
-    file_path.push(file_name);
-
-    std::fs::write(file_path.clone(), data.encode()).map_err(|error| Error::ObjectSaveError {
-        error,
-        path: file_path,
-    })?;
-
-    Ok(())
+    FileStorage::new(db_dir).put_bytes(on_off_chain, user, file_name, data.encode())
 }
 
 /// Utility function to read an object that implements the Encode trait from file.
@@ -538,6 +1028,44 @@ pub fn load_object_from<T: Decode>(file_path: PathBuf) -> Result<T, Error> {
     })
 }
 
+/// Freezes the user's account for the ticker. No transfer in or out of a frozen account is
+/// validated until the account is unfrozen. The flag is kept in its own `{ticker}_frozen` file
+/// next to the account, instead of in the account's memo, since the memo is defined by the
+/// cryptography library.
+pub fn freeze_account(db_dir: PathBuf, user: &str, ticker: &String) -> Result<(), Error> {
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        user,
+        &user_frozen_account_file(ticker),
+        &true,
+    )
+}
+
+/// Lifts the freeze that `freeze_account` has put on the user's account for the ticker.
+pub fn unfreeze_account(db_dir: PathBuf, user: &str, ticker: &String) -> Result<(), Error> {
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        user,
+        &user_frozen_account_file(ticker),
+        &false,
+    )
+}
+
+/// Returns true if the user's account for the ticker is frozen. The accounts that have never
+/// been frozen have no freeze flag on the chain, but a flag that can not be read is an error.
+pub fn is_account_frozen(
+    storage: &dyn Storage,
+    user: &str,
+    ticker: &String,
+) -> Result<bool, Error> {
+    match storage.get(ON_CHAIN_DIR, user, &user_frozen_account_file(ticker)) {
+        Err(Error::FileReadError { error, .. }) if error.kind() == ErrorKind::NotFound => Ok(false),
+        result => result,
+    }
+}
+
 /// Helper function to save a config file to `cfg_path`.
 pub fn save_config<T>(cfg_path: Option<PathBuf>, cfg: &T)
 where
@@ -586,11 +1114,12 @@ pub fn create_rng_from_seed(seed: Option<String>) -> Result<StdRng, Error> {
     Ok(StdRng::from_seed(seed))
 }
 
-/// Reads the account mapping from disk. Returns a map of account id to (user_name, ticker, tx_id).
+/// Reads the account mapping from the storage. Returns a map of account id to
+/// (user_name, ticker, tx_id).
 #[inline]
-pub fn load_account_map(db_dir: PathBuf) -> HashMap<String, (String, String, u32)> {
+pub fn load_account_map(storage: &dyn Storage) -> HashMap<String, (String, String, u32)> {
     let mapping: Result<HashMap<String, (String, String, u32)>, Error> =
-        load_from_file(db_dir, OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, USER_ACCOUNT_MAP);
+        storage.get_json(OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, USER_ACCOUNT_MAP);
     match mapping {
         Err(_error) => HashMap::new(),
         Ok(mapping) => mapping,
@@ -606,7 +1135,7 @@ pub fn update_account_map(
     account_id: EncryptedAssetId,
     tx_id: u32,
 ) -> Result<(), Error> {
-    let mut mapping = load_account_map(db_dir.clone());
+    let mut mapping = load_account_map(&FileStorage::new(db_dir.clone()));
     mapping.insert(
         PrintableAccountId(account_id.encode()).to_string(),
         (user, ticker, tx_id),
@@ -624,15 +1153,120 @@ pub fn update_account_map(
 #[inline]
 pub fn get_user_ticker_from(
     account_id: EncryptedAssetId,
-    db_dir: PathBuf,
+    storage: &dyn Storage,
 ) -> Result<(String, String, u32), Error> {
-    let mapping = load_account_map(db_dir.clone());
-    let (user, ticker, tx_id) = mapping
-        .get(&PrintableAccountId(account_id.encode()).to_string())
-        .ok_or(Error::AccountIdNotFound {
-            account_id: PrintableAccountId(account_id.encode()).to_string(),
-        })?;
-    Ok((user.clone(), ticker.clone(), tx_id.clone()))
+    AccountIndex::load(storage).get(account_id)
+}
+
+/// The validated accounts on the chain, keyed both by account id and by (user, ticker).
+#[derive(Default)]
+pub struct ValidatedAccounts {
+    by_id: HashMap<String, (String, String)>,
+    by_owner: HashMap<(String, String), String>,
+}
+
+impl ValidatedAccounts {
+    /// Reads all the validated accounts from the chain.
+    pub fn load(storage: &dyn Storage) -> Result<Self, Error> {
+        let suffix = format!("_{}", VALIDATED_PUBLIC_ACCOUNT_FILE);
+        let mut validated = Self::default();
+        for user in storage.owners(ON_CHAIN_DIR)? {
+            if user == COMMON_OBJECTS_DIR {
+                continue;
+            }
+            for file_name in storage.list(ON_CHAIN_DIR, &user)? {
+                if !file_name.ends_with(&suffix) {
+                    continue;
+                }
+                let account: OrderedPubAccount = storage.get(ON_CHAIN_DIR, &user, &file_name)?;
+                let ticker = file_name[..file_name.len() - suffix.len()].to_string();
+                validated.insert(
+                    PrintableAccountId(account.pub_account.enc_asset_id.encode()).to_string(),
+                    user.clone(),
+                    ticker,
+                );
+            }
+        }
+        Ok(validated)
+    }
+
+    /// Returns the (user, ticker) of the validated account with the given id.
+    pub fn owner(&self, account_id: &str) -> Option<&(String, String)> {
+        self.by_id.get(account_id)
+    }
+
+    /// Returns the id of the user's validated account for the ticker.
+    pub fn account_id(&self, user: &str, ticker: &str) -> Option<&String> {
+        self.by_owner.get(&(user.to_string(), ticker.to_string()))
+    }
+
+    /// Records a newly validated account.
+    pub fn insert(&mut self, account_id: String, user: String, ticker: String) {
+        self.by_owner
+            .insert((user.clone(), ticker.clone()), account_id.clone());
+        self.by_id.insert(account_id, (user, ticker));
+    }
+}
+
+/// The account mapping, read once and shared by all the lookups of a validation pass, instead
+/// of reading the mapping file again for every lookup. The validated accounts are read on first
+/// use, and are kept up to date as the accounts of the pass are validated.
+pub struct AccountIndex {
+    accounts: HashMap<String, (String, String, u32)>,
+    validated: Mutex<Option<ValidatedAccounts>>,
+}
+
+impl AccountIndex {
+    pub fn load(storage: &dyn Storage) -> Self {
+        Self {
+            accounts: load_account_map(storage),
+            validated: Mutex::new(None),
+        }
+    }
+
+    /// Returns the (user, ticker, tx_id) of the account.
+    pub fn get(&self, account_id: EncryptedAssetId) -> Result<(String, String, u32), Error> {
+        let account_id = PrintableAccountId(account_id.encode()).to_string();
+        self.accounts
+            .get(&account_id)
+            .cloned()
+            .ok_or(Error::AccountIdNotFound { account_id })
+    }
+
+    /// Calls `f` with the validated accounts of `index`, or with the validated accounts read from
+    /// `storage` if no index is supplied.
+    pub fn with_validated<T>(
+        index: Option<&Self>,
+        storage: &dyn Storage,
+        f: impl FnOnce(&mut ValidatedAccounts) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let index = match index {
+            Some(index) => index,
+            None => return f(&mut ValidatedAccounts::load(storage)?),
+        };
+        let mut validated = index
+            .validated
+            .lock()
+            .expect("The validated accounts are poisoned.");
+        if validated.is_none() {
+            *validated = Some(ValidatedAccounts::load(storage)?);
+        }
+        f(validated
+            .as_mut()
+            .expect("The validated accounts are loaded."))
+    }
+
+    /// Looks the account up in `index`, or reads the mapping file if no index is supplied.
+    pub fn lookup(
+        index: Option<&Self>,
+        account_id: EncryptedAssetId,
+        storage: &dyn Storage,
+    ) -> Result<(String, String, u32), Error> {
+        match index {
+            Some(index) => index.get(account_id),
+            None => get_user_ticker_from(account_id, storage),
+        }
+    }
 }
 
 /// Searches the on-chain transactions to find the last transaction that the give user has submitted
@@ -642,9 +1276,9 @@ pub fn last_ordering_state(
     user: String,
     last_processed_tx_counter_from_account: Option<u32>,
     current_tx_id: u32,
-    db_dir: PathBuf,
+    storage: &dyn Storage,
 ) -> Result<OrderingState, Error> {
-    let all_tx_files = all_unverified_tx_files(db_dir)?;
+    let all_tx_files = all_unverified_tx_files(storage)?;
 
     let parsed: (Option<Error>, Option<u32>, Option<u32>, CoreTransaction) = all_tx_files
         .into_iter()
@@ -658,8 +1292,8 @@ pub fn last_ordering_state(
         })
         .map(|res| {
             // Convert the files into tx objects.
-            res.map(|(tx_id, user, state, tx_file_path)| {
-                load_tx_file(tx_id, user, state, tx_file_path)
+            res.map(|(tx_id, user, state, tx_file)| {
+                load_tx_file(storage, tx_id, user, state, tx_file)
                     .map_or_else(|_| CoreTransaction::Invalid, |tx| tx) // Remove Result.
             })
         })
@@ -721,11 +1355,11 @@ pub fn last_ordering_state(
 #[inline]
 pub fn load_tx_between_counters(
     user: &String,
-    db_dir: PathBuf,
+    storage: &dyn Storage,
     start: u32,
     end: u32,
 ) -> Result<Vec<CoreTransaction>, Error> {
-    all_unverified_tx_files(db_dir)?
+    all_unverified_tx_files(storage)?
         .into_iter()
         .map(|tx| parse_tx_name(tx))
         .filter(|res| {
@@ -735,8 +1369,8 @@ pub fn load_tx_between_counters(
         })
         .map(|res| {
             // Convert the files into tx objects.
-            res.map(|(tx_id, user, state, tx_file_path)| {
-                load_tx_file(tx_id, user, state, tx_file_path)
+            res.map(|(tx_id, user, state, tx_file)| {
+                load_tx_file(storage, tx_id, user, state, tx_file)
                     .map_or_else(|_| CoreTransaction::Invalid, |tx| tx) // Remove Result.
             })
         })
@@ -753,14 +1387,50 @@ pub fn load_tx_between_counters(
         .collect()
 }
 
+/// Returns the balance that the user can spend from the account for the ticker: the validated
+/// balance minus the amounts of the user's outgoing transfers that are not validated yet. This
+/// is the same balance that a new transfer of the user would be created against. It only
+/// reads the on-chain data and does not change any state.
+pub fn pending_balance(
+    user: &String,
+    ticker: &String,
+    db_dir: PathBuf,
+) -> Result<EncryptedAmount, Error> {
+    let storage: &dyn Storage = &FileStorage::new(db_dir.clone());
+    let ordered_pub_account: OrderedPubAccount =
+        storage.get(ON_CHAIN_DIR, user, &user_public_account_file(ticker))?;
+    let balance: EncryptedAmount = storage.get(
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_balance_file(ticker),
+    )?;
+    // Every transaction of the user comes before a transaction with the largest tx_id.
+    let ordering_state = last_ordering_state(
+        user.clone(),
+        ordered_pub_account.last_processed_tx_counter,
+        u32::MAX,
+        storage,
+    )?;
+    compute_enc_pending_balance(
+        user,
+        ordering_state,
+        ordered_pub_account.last_processed_tx_counter,
+        balance,
+        storage,
+        db_dir,
+    )
+}
+
 /// Searches the on-chain data for all pending transactions that decreased the balance of the
-/// given user and computes the pending balance.
+/// given user and computes the pending balance. The `db_dir` is only used to decrypt the amounts
+/// for the debug log.
 #[inline]
 pub fn compute_enc_pending_balance(
     sender: &String,
     ordering_state: OrderingState, // The state at the time of creating the last transaction.
     last_processed_tx_counter: Option<u32>, // The current last processed tx counter.
     enc_balance_in_account: EncryptedAmount,
+    storage: &dyn Storage,
     db_dir: PathBuf,
 ) -> Result<EncryptedAmount, Error> {
     if last_processed_tx_counter < ordering_state.last_processed_tx_counter {
@@ -775,7 +1445,7 @@ pub fn compute_enc_pending_balance(
     }
     let transfer_inits = load_tx_between_counters(
         sender,
-        db_dir.clone(),
+        storage,
         start,
         ordering_state.last_pending_tx_counter,
     )?
@@ -818,21 +1488,77 @@ pub fn compute_enc_pending_balance(
             let account_id = tx.memo.sender_account_id;
             debug!(
                 "------> decremented by {}.",
-                debug_decrypt(account_id, tx.memo.enc_amount_using_sender, db_dir.clone())?
+                debug_decrypt_for_log(account_id, tx.memo.enc_amount_using_sender, db_dir.clone())
             );
         }
     }
     Ok(pending_balance)
 }
 
+/// Returns the ids of the unverified transactions of the given account that are not ready for
+/// validation yet, e.g. a transfer that is still waiting for the receiver to finalize it or for
+/// the mediator to justify it. These transactions prevent the pending balance of the account
+/// from being settled.
+pub fn blocking_transactions(
+    account_id: EncryptedAssetId,
+    storage: &dyn Storage,
+) -> Result<Vec<u32>, Error> {
+    let all_txs = load_all_unverified_txs(storage)?;
+    Ok(blocking_tx_ids(&account_id.encode(), &all_txs))
+}
+
+/// Loads all the unverified transactions, regardless of whether they are ready for validation.
+pub(crate) fn load_all_unverified_txs(
+    storage: &dyn Storage,
+) -> Result<Vec<CoreTransaction>, Error> {
+    all_unverified_tx_files(storage)?
+        .into_iter()
+        .map(|tx| parse_tx_name(tx))
+        .map(|res| {
+            res.and_then(|(tx_id, user, state, tx_file)| {
+                load_tx_file(storage, tx_id, user, state, tx_file)
+            })
+        })
+        .collect()
+}
+
+/// Returns the sorted ids of the transactions in `all_txs` that block the account with the given
+/// encoded id. See `blocking_transactions`.
+pub(crate) fn blocking_tx_ids(account_id: &[u8], all_txs: &[CoreTransaction]) -> Vec<u32> {
+    // A transaction that has reached a ready state is no longer blocking, even though the
+    // files of its earlier states are still around.
+    let ready: HashSet<u32> = all_txs
+        .iter()
+        .filter(|tx| tx.is_ready_for_validation())
+        .filter_map(|tx| tx.tx_id())
+        .collect();
+
+    let mut blocking: Vec<u32> = all_txs
+        .iter()
+        .filter(|tx| !tx.is_ready_for_validation() && tx.involves_account(account_id))
+        .filter_map(|tx| tx.tx_id())
+        .filter(|tx_id| !ready.contains(tx_id))
+        .collect();
+    blocking.sort();
+    blocking.dedup();
+    blocking
+}
+
 /// Searches the on-chain data and returns all the transactions since the last verification.
-pub fn all_unverified_tx_files(db_dir: PathBuf) -> Result<Vec<String>, Error> {
-    let start = last_verified_tx_id(db_dir.clone());
-    let mut dir = db_dir.clone();
-    dir.push(ON_CHAIN_DIR);
-    dir.push(COMMON_OBJECTS_DIR);
+pub fn all_unverified_tx_files(storage: &dyn Storage) -> Result<Vec<String>, Error> {
+    let start = last_verified_tx_id(storage);
+    tx_files_matching(storage, |tx_id| tx_id as i32 > start)
+}
 
-    let mut files = vec![];
+/// Searches the on-chain data and returns all the transactions whose tx_id is in the inclusive
+/// range `[from, to]`, regardless of whether they have already been verified.
+pub fn tx_files_in_range(storage: &dyn Storage, from: u32, to: u32) -> Result<Vec<String>, Error> {
+    tx_files_matching(storage, |tx_id| tx_id >= from && tx_id <= to)
+}
+
+/// Returns the paths of all the entries of the given directory.
+pub(crate) fn read_dir_paths(dir: PathBuf) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = vec![];
     for entry in std::fs::read_dir(dir.clone()).map_err(|error| Error::FileReadError {
         error,
         path: dir.clone(),
@@ -841,63 +1567,69 @@ pub fn all_unverified_tx_files(db_dir: PathBuf) -> Result<Vec<String>, Error> {
             error,
             path: dir.clone(),
         })?;
-        let path = entry.path();
-        if !path.is_dir() {
-            let file_name: &str = path
-                .file_name()
-                .expect("It is a file and therefore, this should never fail!")
-                .to_str()
-                .ok_or(Error::PathBufConversionError)?;
-            if file_name.starts_with("tx_") {
-                let re = Regex::new(r"^tx_([0-9]+)_.*$").map_err(|_| Error::RegexError {
-                    reason: String::from("Failed to compile the transaction id regex"),
-                })?;
-                let caps = re.captures(&file_name).ok_or(Error::RegexError {
-                    reason: format!("Pattern did not match {}", file_name),
+        paths.push(entry.path());
+    }
+    Ok(paths)
+}
+
+/// Searches the on-chain data and returns the names of all the transaction files whose tx_id
+/// satisfies `keep`, sorted by tx_id. The files of the same tx_id are kept in the order they were
+/// listed.
+fn tx_files_matching<F>(storage: &dyn Storage, keep: F) -> Result<Vec<String>, Error>
+where
+    F: Fn(u32) -> bool,
+{
+    let re = Regex::new(r"^tx_([0-9]+)_.*$").map_err(|_| Error::RegexError {
+        reason: String::from("Failed to compile the transaction id regex"),
+    })?;
+    let mut files = vec![];
+    for file_name in storage.list(ON_CHAIN_DIR, COMMON_OBJECTS_DIR)? {
+        if file_name.starts_with("tx_") {
+            let caps = re.captures(&file_name).ok_or(Error::RegexError {
+                reason: format!("Pattern did not match {}", file_name),
+            })?;
+            let tx_id = caps[1]
+                .to_string()
+                .parse::<u32>()
+                .map_err(|_| Error::RegexError {
+                    reason: String::from("failed to convert amount to u32."),
                 })?;
-                let tx_id = caps[1]
-                    .to_string()
-                    .parse::<u32>()
-                    .map_err(|_| Error::RegexError {
-                        reason: String::from("failed to convert amount to u32."),
-                    })?;
-                if tx_id as i32 > start {
-                    files.push(String::from(
-                        path.to_str().ok_or(Error::PathBufConversionError)?,
-                    ));
-                }
+            if keep(tx_id) {
+                files.push((tx_id, file_name));
             }
         }
     }
-    Ok(files)
+    // The directory is listed in an arbitrary order, but the transactions of a batch are
+    // validated in the order of their tx_ids.
+    files.sort_by_key(|(tx_id, _)| *tx_id);
+    Ok(files.into_iter().map(|(_, file)| file).collect())
 }
 
 /// Loads the tx_id of the last verified transaction from an off-chain file.
 #[inline]
-pub fn last_verified_tx_id(db_dir: PathBuf) -> i32 {
+pub fn last_verified_tx_id(storage: &dyn Storage) -> i32 {
     // The file and updated after verification is done.
-    let last_verified: Result<i32, Error> = load_from_file(
-        db_dir,
-        OFF_CHAIN_DIR,
-        COMMON_OBJECTS_DIR,
-        LAST_VALIDATED_TX_ID_FILE,
-    );
+    let last_verified: Result<i32, Error> =
+        storage.get_json(OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, LAST_VALIDATED_TX_ID_FILE);
     match last_verified {
         Err(_) => -1,
         Ok(tx_id) => tx_id,
     }
 }
 
-/// Reads a transaction file and returns the corresponding object.
+/// Reads a transaction file from the on-chain common objects and returns the corresponding
+/// object.
 #[inline]
 pub fn load_tx_file(
+    storage: &dyn Storage,
     tx_id: u32,
     user: String,
     state: String,
-    tx_file_path: String,
+    tx_file: String,
 ) -> Result<CoreTransaction, Error> {
     let tx = if state == AssetTxState::Initialization(TxSubstate::Started).to_string() {
-        let instruction: OrderedAssetInstruction = load_object_from(PathBuf::from(tx_file_path))?;
+        let instruction: OrderedAssetInstruction =
+            storage.get(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &tx_file)?;
         CoreTransaction::IssueInit {
             issue_tx: InitializedAssetTx::decode(&mut &instruction.data[..])
                 .map_err(|_| Error::DecodeError)?,
@@ -908,7 +1640,7 @@ pub fn load_tx_file(
         }
     } else if state == TransferTxState::Initialization(TxSubstate::Started).to_string() {
         let instruction: OrderedTransferInstruction =
-            load_object_from(PathBuf::from(tx_file_path))?;
+            storage.get(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &tx_file)?;
         CoreTransaction::TransferInit {
             tx: InitializedTransferTx::decode(&mut &instruction.data[..])
                 .map_err(|_| Error::DecodeError)?,
@@ -918,7 +1650,7 @@ pub fn load_tx_file(
         }
     } else if state == TransferTxState::Finalization(TxSubstate::Started).to_string() {
         let instruction: OrderedTransferInstruction =
-            load_object_from(PathBuf::from(tx_file_path))?;
+            storage.get(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &tx_file)?;
         CoreTransaction::TransferFinalize {
             tx: FinalizedTransferTx::decode(&mut &instruction.data[..])
                 .map_err(|_| Error::DecodeError)?,
@@ -927,7 +1659,8 @@ pub fn load_tx_file(
             tx_id,
         }
     } else if state == TransferTxState::Justification(TxSubstate::Started).to_string() {
-        let instruction: TransferInstruction = load_object_from(PathBuf::from(tx_file_path))?;
+        let instruction: TransferInstruction =
+            storage.get(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &tx_file)?;
         CoreTransaction::TransferJustify {
             tx: JustifiedTransferTx::decode(&mut &instruction.data[..])
                 .map_err(|_| Error::DecodeError)?,
@@ -936,26 +1669,26 @@ pub fn load_tx_file(
         }
     } else if state.starts_with("ticker#") {
         let ordered_account_tx: OrderedPubAccountTx =
-            load_object_from(PathBuf::from(tx_file_path))?;
+            storage.get(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &tx_file)?;
         CoreTransaction::Account {
             account_tx: ordered_account_tx.account_tx,
             tx_id,
             ordering_state: ordered_account_tx.ordering_state,
         }
     } else {
-        return Err(Error::InvalidTransactionFile { path: tx_file_path });
+        return Err(Error::InvalidTransactionFile { path: tx_file });
     };
     Ok(tx)
 }
 
 /// Use only for debugging purposes.
 #[inline]
-fn debug_decrypt(
+pub(crate) fn debug_decrypt(
     account_id: EncryptedAssetId,
     enc_balance: EncryptedAmount,
     db_dir: PathBuf,
 ) -> Result<u32, Error> {
-    let (user, ticker, _) = get_user_ticker_from(account_id, db_dir.clone())?;
+    let (user, ticker, _) = get_user_ticker_from(account_id, &FileStorage::new(db_dir.clone()))?;
     let ordered_pub_account: OrderedPubAccount = load_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
@@ -976,7 +1709,78 @@ fn debug_decrypt(
         .enc_keys
         .secret
         .decrypt(&enc_balance)
-        .map_err(|error| Error::LibraryError { error })
+        .map_err(Error::from)
+}
+
+/// Use only for debugging purposes. Since the decrypted value is only used in the logs, a failure
+/// is not propagated and a placeholder is returned instead: one for a value that could not be
+/// decrypted (e.g. when it is out of the decryptable range), and another for an account whose
+/// keys could not be loaded.
+#[inline]
+fn debug_decrypt_for_log(
+    account_id: EncryptedAssetId,
+    enc_balance: EncryptedAmount,
+    db_dir: PathBuf,
+) -> String {
+    match debug_decrypt(account_id, enc_balance, db_dir) {
+        Ok(value) => value.to_string(),
+        Err(error) => {
+            debug!("Failed to decrypt the value for logging: {:?}", error);
+            match error {
+                Error::LibraryError { .. } => String::from(OUT_OF_RANGE_PLACEHOLDER),
+                _ => String::from(ACCOUNT_NOT_FOUND_PLACEHOLDER),
+            }
+        }
+    }
+}
+
+/// Formats an amount, given in the smallest unit of the asset, as a fixed-point decimal string
+/// with `decimals` digits after the decimal point. For example, `12345` with 2 decimals
+/// is formatted as `123.45`.
+pub fn format_amount(value: u64, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return value.to_string();
+    }
+    let digits = format!("{:0>width$}", value, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    format!("{}.{}", integer, fraction)
+}
+
+/// Parses a fixed-point decimal string into an amount in the smallest unit of the asset.
+/// This is the inverse of `format_amount`. Fails if the string has more than `decimals`
+/// digits after the decimal point, or if the amount does not fit in a `u64`.
+pub fn parse_amount(amount: &str, decimals: u8) -> Result<u64, Error> {
+    let invalid = |reason: &str| Error::InvalidAmount {
+        amount: amount.to_string(),
+        reason: reason.to_string(),
+    };
+    let (integer, fraction) = match amount.find('.') {
+        Some(index) => (&amount[..index], &amount[index + 1..]),
+        None => (amount, ""),
+    };
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if integer.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+        return Err(invalid("not a decimal number"));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(invalid(&format!("more than {} decimal places", decimals)));
+    }
+
+    let scale = 10u64
+        .checked_pow(decimals as u32)
+        .ok_or_else(|| invalid("too many decimals"))?;
+    let fraction_scale = 10u64.pow((decimals as usize - fraction.len()) as u32);
+    let integer: u64 = integer.parse().map_err(|_| invalid("too large"))?;
+    let fraction: u64 = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse().map_err(|_| invalid("too large"))?
+    };
+    integer
+        .checked_mul(scale)
+        .and_then(|value| value.checked_add(fraction * fraction_scale))
+        .ok_or_else(|| invalid("too large"))
 }
 
 /// Use only for debugging purposes.
@@ -1002,7 +1806,7 @@ pub fn debug_decrypt_account_balance(
         .enc_keys
         .secret
         .decrypt(&enc_balance)
-        .map_err(|error| Error::LibraryError { error })
+        .map_err(Error::from)
 }
 
 /// Use only for debugging purposes.
@@ -1024,5 +1828,450 @@ pub fn debug_decrypt_base64_account_balance(
     scrt.enc_keys
         .secret
         .decrypt(&enc_balance)
-        .map_err(|error| Error::LibraryError { error })
+        .map_err(Error::from)
+}
+
+/// A ledger that the tests build with the same functions as the CLIs.
+#[cfg(test)]
+pub(crate) mod test_chain {
+    use super::*;
+    use crate::storage::InMemoryStorage;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::{process_create_tx, process_finalize_tx},
+        chain_setup::process_asset_id_creation,
+        justify::{justify_asset_transfer_transaction, process_create_mediator, TransferRejection},
+        validate::validate_all_pending,
+    };
+
+    /// The tx_id of alice's issuance in a chain created by `TestChain::funded`.
+    pub const ISSUANCE_TX_ID: u32 = 2;
+
+    /// The ACME accounts of alice and bob, created with tx_ids 0 and 1, and mike's mediator
+    /// account, in a fresh directory under `chain_dir/unittest`.
+    pub struct TestChain {
+        pub db_dir: PathBuf,
+        pub ticker: String,
+        pub alice: String,
+        pub bob: String,
+        pub mike: String,
+    }
+
+    impl TestChain {
+        /// Creates the asset and the accounts, and validates the accounts.
+        pub fn new(name: &str) -> Self {
+            let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            db_dir.push("chain_dir/unittest");
+            db_dir.push(name);
+            let _ = std::fs::remove_dir_all(db_dir.clone());
+
+            let chain = Self {
+                db_dir,
+                ticker: String::from("ACME"),
+                alice: String::from("alice"),
+                bob: String::from("bob"),
+                mike: String::from("mike"),
+            };
+            process_asset_id_creation(chain.db_dir.clone(), vec![chain.ticker.clone()]).unwrap();
+            for (tx_id, user) in [&chain.alice, &chain.bob].iter().enumerate() {
+                process_create_account(
+                    Some(gen_seed()),
+                    chain.db_dir.clone(),
+                    chain.ticker.clone(),
+                    user.to_string(),
+                    false,
+                    tx_id as u32,
+                    false,
+                )
+                .unwrap();
+            }
+            process_create_mediator(gen_seed(), chain.db_dir.clone(), chain.mike.clone()).unwrap();
+            chain.validate();
+            chain
+        }
+
+        /// Same as `new`, and alice's issuance of 50 with `ISSUANCE_TX_ID` is validated too.
+        pub fn funded(name: &str) -> Self {
+            let chain = Self::new(name);
+            chain.issue(ISSUANCE_TX_ID, 50);
+            chain.validate();
+            chain
+        }
+
+        /// Same as `funded`, and a transfer of 30 from alice to bob is justified, but not
+        /// validated. Returns the chain and the tx_id of the transfer.
+        pub fn with_transfer(name: &str) -> (Self, u32) {
+            let chain = Self::funded(name);
+            let tx_id = ISSUANCE_TX_ID + 1;
+            chain.transfer(tx_id, 30);
+            (chain, tx_id)
+        }
+
+        pub fn validate(&self) {
+            validate_all_pending(
+                self.db_dir.clone(),
+                ReconciliationStrategy::default(),
+                LockMode::default(),
+            )
+            .unwrap();
+        }
+
+        /// Alice issues `amount`.
+        pub fn issue(&self, tx_id: u32, amount: u32) {
+            process_issue_asset(
+                gen_seed(),
+                self.db_dir.clone(),
+                self.alice.clone(),
+                self.ticker.clone(),
+                amount,
+                false,
+                tx_id,
+                false,
+            )
+            .unwrap();
+        }
+
+        /// Alice initiates a transfer of `amount` to bob.
+        pub fn create_transfer(&self, tx_id: u32, amount: u32) {
+            process_create_tx(
+                gen_seed(),
+                self.db_dir.clone(),
+                self.alice.clone(),
+                self.bob.clone(),
+                self.mike.clone(),
+                self.ticker.clone(),
+                amount,
+                false,
+                tx_id,
+                false,
+            )
+            .unwrap();
+        }
+
+        /// Bob finalizes the transfer.
+        pub fn finalize_transfer(&self, tx_id: u32, amount: u32) {
+            process_finalize_tx(
+                gen_seed(),
+                self.db_dir.clone(),
+                self.alice.clone(),
+                self.bob.clone(),
+                self.ticker.clone(),
+                amount,
+                false,
+                tx_id,
+                false,
+            )
+            .unwrap();
+        }
+
+        /// Mike justifies the transfer, or rejects it if `reject` is set.
+        pub fn justify_transfer(&self, tx_id: u32, reject: bool) {
+            justify_asset_transfer_transaction(
+                self.db_dir.clone(),
+                self.alice.clone(),
+                self.bob.clone(),
+                self.mike.clone(),
+                self.ticker.clone(),
+                gen_seed(),
+                false,
+                tx_id,
+                reject.then_some(TransferRejection::default()),
+                false,
+            )
+            .unwrap();
+        }
+
+        /// Runs a transfer of `amount` from alice to bob up to its justification.
+        pub fn transfer(&self, tx_id: u32, amount: u32) {
+            self.create_transfer(tx_id, amount);
+            self.finalize_transfer(tx_id, amount);
+            self.justify_transfer(tx_id, false);
+        }
+
+        pub fn account(&self, user: &str) -> OrderedPubAccount {
+            load_object(
+                self.db_dir.clone(),
+                ON_CHAIN_DIR,
+                user,
+                &user_public_account_file(&self.ticker),
+            )
+            .unwrap()
+        }
+
+        pub fn encrypted_balance(&self, user: &str) -> EncryptedAmount {
+            load_object(
+                self.db_dir.clone(),
+                ON_CHAIN_DIR,
+                user,
+                &user_public_account_balance_file(&self.ticker),
+            )
+            .unwrap()
+        }
+
+        /// Returns the decrypted validated balance of the user.
+        pub fn balance(&self, user: &str) -> u32 {
+            debug_decrypt(
+                self.account(user).pub_account.enc_asset_id,
+                self.encrypted_balance(user),
+                self.db_dir.clone(),
+            )
+            .unwrap()
+        }
+
+        /// Drops the accounts of `user` from the account map, so that the transactions that
+        /// reference them fail to look them up.
+        pub fn forget_account(&self, user: &str) {
+            let mut mapping = load_account_map(&FileStorage::new(self.db_dir.clone()));
+            mapping.retain(|_, (account_user, _, _)| account_user != user);
+            save_to_file(
+                self.db_dir.clone(),
+                OFF_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                USER_ACCOUNT_MAP,
+                &mapping,
+            )
+            .unwrap();
+        }
+
+        /// Returns a copy of the on-chain and off-chain objects in memory.
+        pub fn in_memory(&self) -> InMemoryStorage {
+            let storage = InMemoryStorage::default();
+            let disk = FileStorage::new(self.db_dir.clone());
+            for on_off_chain in &[ON_CHAIN_DIR, OFF_CHAIN_DIR] {
+                for owner in disk.owners(on_off_chain).unwrap() {
+                    for name in disk.list(on_off_chain, &owner).unwrap() {
+                        let data = disk.get_bytes(on_off_chain, &owner, &name).unwrap();
+                        storage
+                            .put_bytes(on_off_chain, &owner, &name, data)
+                            .unwrap();
+                    }
+                }
+            }
+            storage
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_chain::TestChain;
+    use cryptography::asset_proofs::{CommitmentWitness, ElgamalSecretKey};
+
+    #[test]
+    fn test_tx_files_are_sorted_by_tx_id() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/sorted_tx_files");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+        let mut tx_dir = db_dir.clone();
+        tx_dir.push(ON_CHAIN_DIR);
+        tx_dir.push(COMMON_OBJECTS_DIR);
+        std::fs::create_dir_all(tx_dir.clone()).unwrap();
+        for tx_id in [10, 9, 2].iter() {
+            std::fs::write(tx_dir.join(format!("tx_{}_alice_state.json", tx_id)), b"").unwrap();
+        }
+
+        let tx_ids: Vec<u32> = all_unverified_tx_files(&FileStorage::new(db_dir))
+            .unwrap()
+            .into_iter()
+            .map(|tx_file| parse_tx_name(tx_file).unwrap().0)
+            .collect();
+        assert_eq!(tx_ids, vec![2, 9, 10]);
+    }
+
+    #[cfg(feature = "audit")]
+    #[test]
+    fn test_audit_log_keeps_the_entries_in_order() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/audit_log");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+
+        let mutation = |tx_id: u32, new_balance: u32| BalanceMutation {
+            user: String::from("alice"),
+            ticker: String::from("ACME"),
+            applied: vec![(tx_id, Direction::Incoming)],
+            old_balance: Some(0),
+            new_balance: Some(new_balance),
+            timestamp: 0,
+        };
+        append_to_audit_log(db_dir.clone(), &mutation(1, 10)).unwrap();
+        append_to_audit_log(db_dir.clone(), &mutation(2, 20)).unwrap();
+        assert_eq!(
+            load_audit_log(db_dir).unwrap(),
+            vec![mutation(1, 10), mutation(2, 20)]
+        );
+    }
+
+    #[test]
+    fn test_save_is_retried_on_file_contention() {
+        let contention = || Error::ObjectSaveError {
+            error: std::io::Error::from(std::io::ErrorKind::WouldBlock),
+            path: PathBuf::from("test"),
+        };
+
+        let mut attempts = 0;
+        let result = retry_on_contention(2, || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(contention())
+            } else {
+                Ok(())
+            }
+        });
+        assert!(result.is_ok());
+
+        let mut attempts = 0;
+        let result = retry_on_contention(2, || {
+            attempts += 1;
+            Err(contention())
+        });
+        assert!(result.unwrap_err().is_file_contention());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_freeze_and_unfreeze_account() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/freeze_account");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+
+        let ticker = String::from("ACME");
+        let storage: &dyn Storage = &FileStorage::new(db_dir.clone());
+        assert!(!is_account_frozen(storage, "alice", &ticker).unwrap());
+        freeze_account(db_dir.clone(), "alice", &ticker).unwrap();
+        assert!(is_account_frozen(storage, "alice", &ticker).unwrap());
+        assert!(!is_account_frozen(storage, "bob", &ticker).unwrap());
+        unfreeze_account(db_dir.clone(), "alice", &ticker).unwrap();
+        assert!(!is_account_frozen(storage, "alice", &ticker).unwrap());
+
+        // A freeze flag that can not be read is not mistaken for an account that is not frozen.
+        std::fs::write(
+            construct_path(
+                db_dir,
+                ON_CHAIN_DIR,
+                "alice",
+                &user_frozen_account_file(&ticker),
+            ),
+            b"",
+        )
+        .unwrap();
+        assert!(is_account_frozen(storage, "alice", &ticker).is_err());
+    }
+
+    #[test]
+    fn test_merge_validation_results() {
+        let failed = |user: &str, failure| ValidationResult::failed(user, "ACME", failure);
+        let merged = failed("alice", ValidationFailure::ProofInvalid)
+            .merge(failed("alice", ValidationFailure::MissingObject))
+            .unwrap();
+        assert_eq!(merged.failure(), Some(&ValidationFailure::ProofInvalid));
+        assert!(merged.amount.is_none());
+
+        match failed("alice", ValidationFailure::Unknown)
+            .merge(failed("bob", ValidationFailure::Unknown))
+        {
+            Err(Error::ConflictingValidationResults { user, .. }) => assert_eq!(user, "alice"),
+            _ => panic!("Expected the results of different accounts to conflict."),
+        }
+    }
+
+    #[test]
+    fn test_merge_successful_validation_results() {
+        let mut rng = StdRng::from_seed([42u8; 32]);
+        let secret_key = ElgamalSecretKey::new(Scalar::random(&mut rng));
+        let mut incoming = |value: u32| {
+            let witness = CommitmentWitness::new(Scalar::from(value), Scalar::random(&mut rng));
+            ValidationResult {
+                user: String::from("alice"),
+                ticker: String::from("ACME"),
+                direction: Direction::Incoming,
+                amount: Some(secret_key.get_public_key().encrypt(&witness).into()),
+                failure: None,
+                persisted: true,
+            }
+        };
+        let merged = incoming(20).merge(incoming(30)).unwrap();
+        assert!(merged.failure().is_none());
+        assert_eq!(merged.direction, Direction::Incoming);
+        assert!(merged.persisted);
+        assert_eq!(secret_key.decrypt(&merged.amount.unwrap().0).unwrap(), 50);
+    }
+
+    #[test]
+    fn test_debug_decrypt_for_log_is_best_effort() {
+        let value = debug_decrypt_for_log(
+            non_empty_account_id(),
+            non_empty_account_id(),
+            PathBuf::from("non-existent-db-dir"),
+        );
+        assert_eq!(value, ACCOUNT_NOT_FOUND_PLACEHOLDER);
+
+        // A negative balance is out of the decryptable range.
+        let chain = TestChain::new("debug_decrypt_out_of_range");
+        let mut rng = StdRng::from_seed([42u8; 32]);
+        let account = chain.account(&chain.bob).pub_account;
+        let one = account.owner_enc_pub_key.encrypt(&CommitmentWitness::new(
+            Scalar::from(1u32),
+            Scalar::random(&mut rng),
+        ));
+        let balance = chain.encrypted_balance(&chain.bob) - one;
+        let value = debug_decrypt_for_log(account.enc_asset_id, balance, chain.db_dir.clone());
+        assert_eq!(value, OUT_OF_RANGE_PLACEHOLDER);
+    }
+
+    #[test]
+    fn test_format_and_parse_amount() {
+        assert_eq!(format_amount(12345, 2), "123.45");
+        assert_eq!(format_amount(5, 3), "0.005");
+        assert_eq!(format_amount(12345, 0), "12345");
+
+        assert_eq!(parse_amount("123.45", 2).unwrap(), 12345);
+        assert_eq!(parse_amount("123.4", 2).unwrap(), 12340);
+        assert_eq!(parse_amount("123", 2).unwrap(), 12300);
+        assert_eq!(parse_amount(&format_amount(5, 3), 3).unwrap(), 5);
+
+        // Over-precision is rejected instead of being rounded.
+        assert!(parse_amount("1.234", 2).is_err());
+        assert!(parse_amount("1.5", 0).is_err());
+        assert!(parse_amount("abc", 2).is_err());
+    }
+
+    #[test]
+    fn test_referenced_keys_of_a_justified_transfer() {
+        let (chain, _) = TestChain::with_transfer("referenced_keys");
+        let db_dir = chain.db_dir.clone();
+        let storage = chain.in_memory();
+
+        let justified = load_all_unverified_txs(&storage)
+            .unwrap()
+            .into_iter()
+            .find(|tx| match tx {
+                CoreTransaction::TransferJustify { .. } => true,
+                _ => false,
+            })
+            .unwrap();
+        let account_key = |user: &str| chain.account(user).pub_account.owner_enc_pub_key.encode();
+        let mediator_key: EncryptionPubKey = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &chain.mike,
+            MEDIATOR_PUBLIC_ACCOUNT_FILE,
+        )
+        .unwrap();
+        let keys: Vec<Vec<u8>> = justified
+            .referenced_keys(&storage)
+            .unwrap()
+            .iter()
+            .map(|key| key.encode())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                account_key(&chain.alice),
+                account_key(&chain.bob),
+                mediator_key.encode()
+            ]
+        );
+    }
 }
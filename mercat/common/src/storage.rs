@@ -0,0 +1,430 @@
+//! Storage backends for the on-chain and off-chain objects.
+//!
+//! The validator reads and writes the ledger only through a `Storage`: the encoded objects, the
+//! JSON bookkeeping files, such as the `LAST_VALIDATED_TX_ID_FILE` and the account map, the
+//! listing of the transactions, and the locks. Only the debug decryption of the amounts and the
+//! audit log still use the database directory.
+
+use crate::{
+    construct_path, errors::Error, lock_file, read_dir_paths, retry_on_contention, LockMode,
+    DEFAULT_SAVE_RETRIES,
+};
+use codec::{Decode, Encode};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::create_dir_all,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex, RwLock},
+};
+
+/// Holds a lock that `Storage::lock` has taken, until it is dropped.
+pub type StorageLock = Box<dyn Send>;
+
+/// A key-value store for encoded objects. An object is identified by the on/off chain
+/// directory, its owner (a user or `COMMON_OBJECTS_DIR`), and its name. A storage is shared
+/// between the threads that verify the transactions of a batch.
+pub trait Storage: Sync {
+    /// Returns the raw bytes of an object.
+    fn get_bytes(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<Vec<u8>, Error>;
+
+    /// Stores the raw bytes of an object, replacing the previous value if there is any.
+    fn put_bytes(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        data: Vec<u8>,
+    ) -> Result<(), Error>;
+
+    /// Removes an object. It is an error if the object does not exist.
+    fn remove(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<(), Error>;
+
+    /// Returns the names of the objects of the owner, in no particular order. An owner without
+    /// any objects has none.
+    fn list(&self, on_off_chain: &str, owner: &str) -> Result<Vec<String>, Error>;
+
+    /// Returns the owners that have objects in the on/off chain directory, in no particular order.
+    fn owners(&self, on_off_chain: &str) -> Result<Vec<String>, Error>;
+
+    /// Takes an exclusive lock on an object, which need not exist. With `LockMode::FailFast`, a
+    /// lock that is held by someone else is reported as `LedgerLocked` instead of waited for.
+    fn lock(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        mode: LockMode,
+    ) -> Result<StorageLock, Error>;
+}
+
+impl dyn Storage + '_ {
+    /// Reads and decodes an object.
+    pub fn get<T: Decode>(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<T, Error> {
+        let data = self.get_bytes(on_off_chain, owner, name)?;
+        T::decode(&mut &data[..]).map_err(|error| Error::ObjectLoadError {
+            error,
+            path: construct_path(PathBuf::new(), on_off_chain, owner, name),
+        })
+    }
+
+    /// Encodes and stores an object.
+    pub fn put<T: Encode>(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        data: &T,
+    ) -> Result<(), Error> {
+        self.put_bytes(on_off_chain, owner, name, data.encode())
+    }
+
+    /// Reads and deserializes an object that is stored as JSON, like the files of `save_to_file`.
+    pub fn get_json<T: DeserializeOwned>(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+    ) -> Result<T, Error> {
+        let data = self.get_bytes(on_off_chain, owner, name)?;
+        serde_json::from_slice(&data).map_err(|error| Error::ObjectDeserializationError {
+            error,
+            path: construct_path(PathBuf::new(), on_off_chain, owner, name),
+        })
+    }
+
+    /// Serializes and stores an object as JSON.
+    pub fn put_json<T: ?Sized + Serialize>(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        data: &T,
+    ) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(data).map_err(|error| Error::FileWriteError {
+            error,
+            path: construct_path(PathBuf::new(), on_off_chain, owner, name),
+        })?;
+        self.put_bytes(on_off_chain, owner, name, data)
+    }
+}
+
+/// Stores each object in its own file, following the layout of the database directory. An
+/// object is first written to a hidden temporary file, which is then renamed, so that readers
+/// never see a partial write. A write that fails because the file is held by another process is
+/// retried. The locks are advisory locks on `<name>.lock` files.
+pub struct FileStorage {
+    db_dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(db_dir: PathBuf) -> Self {
+        Self { db_dir }
+    }
+
+    /// Returns the names of the entries of the directory that satisfy `keep`. A directory that
+    /// does not exist has no entries.
+    fn entries(&self, dir: PathBuf, keep: impl Fn(&PathBuf) -> bool) -> Result<Vec<String>, Error> {
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut names = vec![];
+        for path in read_dir_paths(dir)? {
+            if !keep(&path) {
+                continue;
+            }
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(Error::PathBufConversionError)?;
+            // The temporary files of the writes and the lock files are not objects.
+            if !name.starts_with('.') && !name.ends_with(".lock") {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Saves an object, without retrying if the file is held by another process.
+    fn put_bytes_once(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let mut file_path = self.db_dir.clone();
+        file_path.push(on_off_chain);
+        file_path.push(owner);
+
+        // The file_path is now the path to the owner directory. Create it if it does not exist.
+        create_dir_all(file_path.clone()).map_err(|error| Error::FileCreationError {
+            error,
+            path: file_path.clone(),
+        })?;
+
+        let mut tmp_path = file_path.clone();
+        tmp_path.push(format!(".{}.tmp", name));
+        file_path.push(name);
+        std::fs::write(tmp_path.clone(), data).map_err(|error| Error::ObjectSaveError {
+            error,
+            path: file_path.clone(),
+        })?;
+        std::fs::rename(tmp_path, file_path.clone()).map_err(|error| Error::ObjectSaveError {
+            error,
+            path: file_path,
+        })
+    }
+}
+
+impl Storage for FileStorage {
+    fn get_bytes(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<Vec<u8>, Error> {
+        let file_path = construct_path(self.db_dir.clone(), on_off_chain, owner, name);
+        std::fs::read(file_path.clone()).map_err(|error| Error::FileReadError {
+            error,
+            path: file_path,
+        })
+    }
+
+    fn put_bytes(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        retry_on_contention(DEFAULT_SAVE_RETRIES, || {
+            self.put_bytes_once(on_off_chain, owner, name, &data)
+        })
+    }
+
+    fn remove(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<(), Error> {
+        let file_path = construct_path(self.db_dir.clone(), on_off_chain, owner, name);
+        std::fs::remove_file(file_path.clone()).map_err(|error| Error::FileRemovalError {
+            error,
+            path: file_path,
+        })
+    }
+
+    fn list(&self, on_off_chain: &str, owner: &str) -> Result<Vec<String>, Error> {
+        let mut dir = self.db_dir.clone();
+        dir.push(on_off_chain);
+        dir.push(owner);
+        self.entries(dir, |path| !path.is_dir())
+    }
+
+    fn owners(&self, on_off_chain: &str) -> Result<Vec<String>, Error> {
+        let mut dir = self.db_dir.clone();
+        dir.push(on_off_chain);
+        self.entries(dir, |path| path.is_dir())
+    }
+
+    fn lock(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        mode: LockMode,
+    ) -> Result<StorageLock, Error> {
+        let lock = lock_file(self.db_dir.clone(), on_off_chain, owner, name, mode)?;
+        Ok(Box::new(lock))
+    }
+}
+
+/// Keeps the objects in memory. Meant for tests that do not need to persist the ledger.
+#[derive(Default)]
+pub struct InMemoryStorage {
+    objects: RwLock<HashMap<PathBuf, Vec<u8>>>,
+    locks: Arc<(Mutex<HashSet<PathBuf>>, Condvar)>,
+}
+
+impl InMemoryStorage {
+    /// Returns the (owner, name) of all the objects in the on/off chain directory.
+    fn keys(&self, on_off_chain: &str) -> Vec<(String, String)> {
+        let dir = PathBuf::from(on_off_chain);
+        self.objects
+            .read()
+            .expect("The in-memory storage is poisoned.")
+            .keys()
+            .filter_map(|path| {
+                let mut components = path.strip_prefix(&dir).ok()?.iter();
+                let owner = components.next()?.to_str()?.to_string();
+                let name = components.next()?.to_str()?.to_string();
+                Some((owner, name))
+            })
+            .collect()
+    }
+}
+
+/// A lock of the `InMemoryStorage`, which is released when it is dropped.
+struct InMemoryLock {
+    locks: Arc<(Mutex<HashSet<PathBuf>>, Condvar)>,
+    path: PathBuf,
+}
+
+impl Drop for InMemoryLock {
+    fn drop(&mut self) {
+        let (held, released) = &*self.locks;
+        held.lock()
+            .expect("The in-memory locks are poisoned.")
+            .remove(&self.path);
+        released.notify_all();
+    }
+}
+
+impl Storage for InMemoryStorage {
+    fn get_bytes(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<Vec<u8>, Error> {
+        let path = construct_path(PathBuf::new(), on_off_chain, owner, name);
+        self.objects
+            .read()
+            .expect("The in-memory storage is poisoned.")
+            .get(&path)
+            .cloned()
+            .ok_or_else(|| Error::FileReadError {
+                error: io::Error::new(ErrorKind::NotFound, "object not found in memory"),
+                path,
+            })
+    }
+
+    fn put_bytes(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        let path = construct_path(PathBuf::new(), on_off_chain, owner, name);
+        self.objects
+            .write()
+            .expect("The in-memory storage is poisoned.")
+            .insert(path, data);
+        Ok(())
+    }
+
+    fn remove(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<(), Error> {
+        let path = construct_path(PathBuf::new(), on_off_chain, owner, name);
+        match self
+            .objects
+            .write()
+            .expect("The in-memory storage is poisoned.")
+            .remove(&path)
+        {
+            Some(_) => Ok(()),
+            None => Err(Error::FileRemovalError {
+                error: io::Error::new(ErrorKind::NotFound, "object not found in memory"),
+                path,
+            }),
+        }
+    }
+
+    fn list(&self, on_off_chain: &str, owner: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .keys(on_off_chain)
+            .into_iter()
+            .filter(|(object_owner, _)| object_owner == owner)
+            .map(|(_, name)| name)
+            .collect())
+    }
+
+    fn owners(&self, on_off_chain: &str) -> Result<Vec<String>, Error> {
+        let mut owners: Vec<String> = self
+            .keys(on_off_chain)
+            .into_iter()
+            .map(|(owner, _)| owner)
+            .collect();
+        owners.sort();
+        owners.dedup();
+        Ok(owners)
+    }
+
+    fn lock(
+        &self,
+        on_off_chain: &str,
+        owner: &str,
+        name: &str,
+        mode: LockMode,
+    ) -> Result<StorageLock, Error> {
+        let path = construct_path(PathBuf::new(), on_off_chain, owner, name);
+        let (held, released) = &*self.locks;
+        let mut held = held.lock().expect("The in-memory locks are poisoned.");
+        while held.contains(&path) {
+            if mode == LockMode::FailFast {
+                return Err(Error::LedgerLocked { path });
+            }
+            held = released
+                .wait(held)
+                .expect("The in-memory locks are poisoned.");
+        }
+        held.insert(path.clone());
+        Ok(Box::new(InMemoryLock {
+            locks: self.locks.clone(),
+            path,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_object, MediatorRejection, ON_CHAIN_DIR};
+
+    #[test]
+    fn test_file_and_memory_storage_round_trip() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/storage");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+
+        let rejection = MediatorRejection {
+            tx_id: 7,
+            mediator: String::from("mike"),
+            reason_code: Some(3),
+            signature: vec![],
+        };
+        let file_storage = FileStorage::new(db_dir.clone());
+        let memory_storage = InMemoryStorage::default();
+        let backends: [&dyn Storage; 2] = [&file_storage, &memory_storage];
+        for storage in backends.iter() {
+            assert!(storage
+                .get::<MediatorRejection>(ON_CHAIN_DIR, "alice", "rejection")
+                .is_err());
+            storage
+                .put(ON_CHAIN_DIR, "alice", "rejection", &rejection)
+                .unwrap();
+            let loaded: MediatorRejection =
+                storage.get(ON_CHAIN_DIR, "alice", "rejection").unwrap();
+            assert_eq!(loaded.encode(), rejection.encode());
+
+            let lock = storage
+                .lock(ON_CHAIN_DIR, "alice", "rejection", LockMode::Wait)
+                .unwrap();
+            match storage.lock(ON_CHAIN_DIR, "alice", "rejection", LockMode::FailFast) {
+                Err(Error::LedgerLocked { .. }) => {}
+                _ => panic!("Expected the object to be locked."),
+            }
+            drop(lock);
+            storage
+                .lock(ON_CHAIN_DIR, "alice", "rejection", LockMode::FailFast)
+                .unwrap();
+
+            // Neither the temporary files of the writes nor the lock files are listed.
+            assert_eq!(storage.owners(ON_CHAIN_DIR).unwrap(), vec!["alice"]);
+            assert_eq!(
+                storage.list(ON_CHAIN_DIR, "alice").unwrap(),
+                vec!["rejection"]
+            );
+            assert!(storage.list(ON_CHAIN_DIR, "bob").unwrap().is_empty());
+        }
+
+        // The file storage uses the same layout as `load_object`.
+        let loaded: MediatorRejection =
+            load_object(db_dir, ON_CHAIN_DIR, "alice", "rejection").unwrap();
+        assert_eq!(loaded.encode(), rejection.encode());
+
+        for storage in backends.iter() {
+            storage.remove(ON_CHAIN_DIR, "alice", "rejection").unwrap();
+            assert!(storage.list(ON_CHAIN_DIR, "alice").unwrap().is_empty());
+            assert!(storage.remove(ON_CHAIN_DIR, "alice", "rejection").is_err());
+        }
+    }
+}
@@ -1,150 +1,1301 @@
+use crate::storage::{FileStorage, Storage, StorageLock};
 use crate::{
     account_create_transaction_file, all_unverified_tx_files, asset_transaction_file,
-    compute_enc_pending_balance, confidential_transaction_file, debug_decrypt, errors::Error,
-    get_asset_ids, get_user_ticker_from, last_ordering_state, load_object, load_tx_file,
-    parse_tx_name, save_object, save_to_file, user_public_account_balance_file,
-    user_public_account_file, AssetInstruction, CoreTransaction, Direction, OrderedPubAccount,
-    OrderedPubAccountTx, PrintableAccountId, TransferInstruction, ValidationResult,
-    COMMON_OBJECTS_DIR, LAST_VALIDATED_TX_ID_FILE, OFF_CHAIN_DIR, ON_CHAIN_DIR,
+    blocking_tx_ids, compute_enc_pending_balance, confidential_transaction_file, debug_decrypt,
+    debug_decrypt_for_log, errors::Error, get_asset_ids, is_account_frozen, last_ordering_state,
+    last_verified_tx_id, load_account_map, load_all_unverified_txs, load_tx_file,
+    mediator_rejection_file, parse_tx_name, tx_files_in_range, user_public_account_balance_file,
+    user_public_account_file, AccountIndex, AssetInstruction, CoreTransaction, Direction,
+    EncryptedBalance, LockMode, MediatorRejection, OrderedPubAccount, OrderedPubAccountTx,
+    OrderingState, PrintableAccountId, ReconciliationStrategy, TransferInstruction,
+    ValidatedAccounts, ValidationCheckpoint, ValidationFailure, ValidationOptions,
+    ValidationResult, COMMON_OBJECTS_DIR, LAST_VALIDATED_TX_ID_FILE,
+    MEDIATOR_SIGNING_PUBLIC_KEY_FILE, OFF_CHAIN_DIR, ON_CHAIN_DIR, VALIDATION_CHECKPOINT_FILE,
 };
+#[cfg(feature = "audit")]
+use crate::{append_to_audit_log, BalanceMutation};
 use codec::{Decode, Encode};
 use cryptography::mercat::{
     account::AccountValidator, asset::AssetValidator, transaction::TransactionValidator,
     AccountCreatorVerifier, AssetTransactionVerifier, AssetTxState, EncryptedAmount,
-    EncryptedAssetId, InitializedAssetTx, JustifiedTransferTx, PubAccount,
+    EncryptedAssetId, InitializedAssetTx, JustifiedTransferTx, PubAccount, PubAccountTx,
     TransferTransactionVerifier, TransferTxState, TxSubstate,
 };
-use log::{debug, error, info};
+use curve25519_dalek::scalar::Scalar;
+use log::{debug, error, info, warn};
 use metrics::timing;
 use rand::rngs::OsRng;
-use std::{collections::HashSet, path::PathBuf, time::Instant};
+use rayon::prelude::*;
+use schnorrkel::PublicKey;
+#[cfg(feature = "audit")]
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// The number of times the validation of a transaction is retried after a transient error.
+const TRANSIENT_ERROR_RETRIES: u32 = 3;
+
+/// The delay before the first retry. It is doubled for each of the following retries.
+const TRANSIENT_ERROR_BACKOFF: Duration = Duration::from_millis(50);
+
+/// A transaction that was left out of a partially committed batch, along with the error that
+/// it failed with. The tx_id is not known if the transaction could not be loaded.
+#[derive(Debug)]
+pub struct SkippedTransaction {
+    pub tx_id: Option<u32>,
+    pub error: Error,
+}
+
+/// The projected effect of a validation pass on a single account. The decrypted balances are
+/// `None` if the secret account of the user is not available to the validator.
+#[derive(Debug)]
+pub struct ValidationReport {
+    pub user: String,
+    pub ticker: String,
+    pub old_balance: Option<u32>,
+    pub new_balance: Option<u32>,
+    pub old_enc_balance: EncryptedAmount,
+    pub new_enc_balance: EncryptedAmount,
+    /// The transactions whose amounts contributed to the new balance.
+    pub tx_ids: Vec<u32>,
+}
 
-fn load_all_unverified_and_ready(db_dir: PathBuf) -> Result<Vec<CoreTransaction>, Error> {
-    all_unverified_tx_files(db_dir)?
+/// Lazily loads the unverified transactions that are ready for validation. Only the names of
+/// the transaction files are listed upfront, and each file is parsed and loaded on demand.
+pub fn unverified_ready_iter<'a>(
+    storage: &'a dyn Storage,
+) -> Result<impl Iterator<Item = Result<CoreTransaction, Error>> + 'a, Error> {
+    Ok(ready_iter_from(storage, all_unverified_tx_files(storage)?))
+}
+
+fn load_ready_in_range(
+    storage: &dyn Storage,
+    from: u32,
+    to: u32,
+) -> Result<Vec<CoreTransaction>, Error> {
+    load_ready_from(storage, tx_files_in_range(storage, from, to)?)
+}
+
+/// Returns true if the file holds a transaction that has already been validated or rejected.
+fn is_processed_tx_file(tx_file: &str) -> bool {
+    match parse_tx_name(tx_file.to_string()) {
+        Err(_) => false,
+        Ok((_, _, state, _)) => [
+            AssetTxState::Justification(TxSubstate::Validated).to_string(),
+            AssetTxState::Justification(TxSubstate::Rejected).to_string(),
+            TransferTxState::Justification(TxSubstate::Validated).to_string(),
+            TransferTxState::Justification(TxSubstate::Rejected).to_string(),
+        ]
+        .contains(&state),
+    }
+}
+
+/// Returns true if `ready_iter_from` yields an item for the file, without loading it. The files
+/// whose names can not be parsed or loaded are yielded as errors.
+fn is_ready_tx_file(tx_file: &str) -> bool {
+    match parse_tx_name(tx_file.to_string()) {
+        Err(_) => true,
+        Ok((_, _, state, _)) => {
+            !is_processed_tx_file(tx_file)
+                && ![
+                    TransferTxState::Initialization(TxSubstate::Started).to_string(),
+                    TransferTxState::Finalization(TxSubstate::Started).to_string(),
+                ]
+                .contains(&state)
+        }
+    }
+}
+
+fn load_ready_from(
+    storage: &dyn Storage,
+    tx_files: Vec<String>,
+) -> Result<Vec<CoreTransaction>, Error> {
+    ready_iter_from(storage, tx_files).collect()
+}
+
+/// Lazily loads the transactions of `tx_files` that are ready for validation. The validated and
+/// rejected copies of the transactions are stored next to the original ones, and are not loaded.
+fn ready_iter_from<'a>(
+    storage: &'a dyn Storage,
+    tx_files: Vec<String>,
+) -> impl Iterator<Item = Result<CoreTransaction, Error>> + 'a {
+    tx_files
         .into_iter()
+        .filter(|tx_file| !is_processed_tx_file(tx_file))
         .map(|tx| parse_tx_name(tx))
-        .map(|res| match res {
+        .map(move |res| match res {
             Err(error) => Err(error),
-            Ok((tx_id, user, state, tx_file_path)) => {
-                load_tx_file(tx_id, user, state, tx_file_path)
-            }
+            Ok((tx_id, user, state, tx_file)) => load_tx_file(storage, tx_id, user, state, tx_file),
         })
         .filter(|res| res.is_err() || res.as_ref().unwrap().is_ready_for_validation())
-        .collect()
 }
 
-pub fn validate_all_pending(db_dir: PathBuf) -> Result<(), Error> {
-    // TODO: This function should be called when any justify is called. To be fixed in CRYP-131.
-    let all_unverified_and_ready = load_all_unverified_and_ready(db_dir.clone())?;
-    let mut last_tx_id: Option<u32> = None;
+/// The kinds of transactions that the validator verifies. A `VerifierRegistry` maps each kind
+/// to its verifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransactionKind {
+    Account,
+    Issuance,
+    Transfer,
+}
 
-    let mut results: Vec<ValidationResult> = vec![];
-    // For each of them call the validate function and process as needed
-    for tx in all_unverified_and_ready {
+impl TransactionKind {
+    /// Returns the kind of the transaction, or None if it is not ready for validation.
+    pub fn of(tx: &CoreTransaction) -> Option<Self> {
         match tx {
-            CoreTransaction::IssueInit {
-                issue_tx,
-                tx_id,
-                issuer: _,
-                ordering_state: _,
-                amount,
-            } => {
-                let result =
-                    validate_asset_issuance(db_dir.clone(), amount, issue_tx.clone(), tx_id);
-                results.push(result);
-                last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
-            }
-            CoreTransaction::TransferJustify {
-                tx,
-                tx_id,
-                mediator,
-            } => {
-                let account_id = tx.finalized_data.init_data.memo.sender_account_id;
-                let (sender, ticker, _) = get_user_ticker_from(account_id, db_dir.clone())?;
-                let sender_ordered_pub_account: OrderedPubAccount = load_object(
-                    db_dir.clone(),
-                    ON_CHAIN_DIR,
-                    &sender,
-                    &user_public_account_file(&ticker),
-                )?;
-                let sender_account_balance: EncryptedAmount = load_object(
-                    db_dir.clone(),
-                    ON_CHAIN_DIR,
-                    &sender,
-                    &user_public_account_balance_file(&ticker),
-                )?;
-                let ordering_state = last_ordering_state(
-                    sender.clone(),
-                    sender_ordered_pub_account.last_processed_tx_counter,
-                    tx_id,
-                    db_dir.clone(),
-                )?;
-                let pending_balance = compute_enc_pending_balance(
-                    &sender,
-                    ordering_state,
-                    sender_ordered_pub_account.last_processed_tx_counter,
-                    sender_account_balance,
-                    db_dir.clone(),
-                )?;
-                debug!(
-                    "------------> validating tx: {}, pending transfer balance: {}",
-                    tx_id,
-                    debug_decrypt(account_id, pending_balance.clone(), db_dir.clone())?
-                );
-                let (sender_result, receiver_result) =
-                    validate_transaction(db_dir.clone(), tx, mediator, pending_balance, tx_id);
-                results.push(sender_result);
-                results.push(receiver_result);
-                last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
+            CoreTransaction::Account { .. } => Some(TransactionKind::Account),
+            CoreTransaction::IssueInit { .. } => Some(TransactionKind::Issuance),
+            CoreTransaction::TransferJustify { .. } => Some(TransactionKind::Transfer),
+            _ => None,
+        }
+    }
+}
+
+/// Validates a single transaction that is ready for validation. Returns the id of the transaction
+/// and the results that need to be applied to the account balances. When the last argument,
+/// `dry_run`, is set, nothing is written to the on-chain directory.
+pub type TransactionVerifier = Box<
+    dyn Fn(
+            &dyn Storage,
+            Option<&AccountIndex>,
+            PathBuf,
+            CoreTransaction,
+            bool,
+        ) -> Result<(u32, Vec<ValidationResult>), Error>
+        + Send
+        + Sync,
+>;
+
+/// Dispatches each transaction to the verifier that is registered for its kind. The default
+/// registry holds the verifiers of the account creation, issuance, and transfer transactions.
+pub struct VerifierRegistry {
+    verifiers: HashMap<TransactionKind, TransactionVerifier>,
+    retriable: HashSet<TransactionKind>,
+}
+
+impl VerifierRegistry {
+    /// Registers the verifier of a kind of transactions, replacing the previous one if any. The
+    /// verifier is not retried when it fails, since it may have written some of its objects.
+    pub fn register(&mut self, kind: TransactionKind, verifier: TransactionVerifier) {
+        self.retriable.remove(&kind);
+        self.verifiers.insert(kind, verifier);
+    }
+
+    /// Same as `register`, but the verifier is retried when it fails with a transient error.
+    /// Only register verifiers that write nothing before their last fallible step this way.
+    pub fn register_retriable(&mut self, kind: TransactionKind, verifier: TransactionVerifier) {
+        self.register(kind, verifier);
+        self.retriable.insert(kind);
+    }
+
+    /// Returns true if the verifier of the transaction can be retried after a transient error.
+    pub fn is_retriable(&self, tx: &CoreTransaction) -> bool {
+        TransactionKind::of(tx).is_some_and(|kind| self.retriable.contains(&kind))
+    }
+
+    /// Validates the transaction with the verifier of its kind.
+    pub fn verify(
+        &self,
+        storage: &dyn Storage,
+        index: Option<&AccountIndex>,
+        db_dir: PathBuf,
+        tx: CoreTransaction,
+        dry_run: bool,
+    ) -> Result<(u32, Vec<ValidationResult>), Error> {
+        match TransactionKind::of(&tx).and_then(|kind| self.verifiers.get(&kind)) {
+            Some(verifier) => verifier(storage, index, db_dir, tx, dry_run),
+            None => Err(Error::TransactionIsNotReadyForValidation { tx }),
+        }
+    }
+}
+
+impl Default for VerifierRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            verifiers: HashMap::new(),
+            retriable: HashSet::new(),
+        };
+        registry.register(TransactionKind::Account, Box::new(verify_account_file));
+        // The issuance and transfer verifiers only fail before they save the rejected or
+        // validated instruction.
+        registry.register_retriable(TransactionKind::Issuance, Box::new(verify_issuance_tx));
+        registry.register_retriable(TransactionKind::Transfer, Box::new(verify_transfer_tx));
+        registry
+    }
+}
+
+fn verify_issuance_tx(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
+    _db_dir: PathBuf,
+    tx: CoreTransaction,
+    dry_run: bool,
+) -> Result<(u32, Vec<ValidationResult>), Error> {
+    let (issue_tx, tx_id, amount) = match tx {
+        CoreTransaction::IssueInit {
+            issue_tx,
+            tx_id,
+            amount,
+            ..
+        } => (issue_tx, tx_id, amount),
+        tx => return Err(Error::TransactionIsNotReadyForValidation { tx }),
+    };
+    let options = ValidationOptions {
+        dry_run,
+        ..ValidationOptions::default()
+    };
+    let result = validate_asset_issuance(storage, index, amount, issue_tx, tx_id, options);
+    Ok((tx_id, vec![result]))
+}
+
+fn verify_transfer_tx(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
+    db_dir: PathBuf,
+    tx: CoreTransaction,
+    dry_run: bool,
+) -> Result<(u32, Vec<ValidationResult>), Error> {
+    let (tx, tx_id, mediator) = match tx {
+        CoreTransaction::TransferJustify {
+            tx,
+            tx_id,
+            mediator,
+        } => (tx, tx_id, mediator),
+        tx => return Err(Error::TransactionIsNotReadyForValidation { tx }),
+    };
+    if is_rejected_by_mediator(storage, tx_id, &mediator) {
+        info!(
+            "tx-{}: Skipping the transaction, since it was rejected by {}.",
+            tx_id, mediator
+        );
+        if !dry_run {
+            save_rejected_transfer(storage, index, &tx, tx_id)?;
+        }
+        return Ok((tx_id, vec![]));
+    }
+    let account_id = tx.finalized_data.init_data.memo.sender_account_id;
+    let (sender, ticker, _) = AccountIndex::lookup(index, account_id, storage)?;
+    let sender_ordered_pub_account: OrderedPubAccount =
+        storage.get(ON_CHAIN_DIR, &sender, &user_public_account_file(&ticker))?;
+    let sender_account_balance: EncryptedAmount = storage.get(
+        ON_CHAIN_DIR,
+        &sender,
+        &user_public_account_balance_file(&ticker),
+    )?;
+    // The pending balance is only needed to verify a transfer that is not yet applied to the
+    // sender's account.
+    let pending_balance = if is_applied(&sender_ordered_pub_account, tx_id) {
+        sender_account_balance
+    } else {
+        let ordering_state = last_ordering_state(
+            sender.clone(),
+            sender_ordered_pub_account.last_processed_tx_counter,
+            tx_id,
+            storage,
+        )?;
+        compute_enc_pending_balance(
+            &sender,
+            ordering_state,
+            sender_ordered_pub_account.last_processed_tx_counter,
+            sender_account_balance,
+            storage,
+            db_dir.clone(),
+        )?
+    };
+    debug!(
+        "------------> validating tx: {}, pending transfer balance: {}",
+        tx_id,
+        debug_decrypt_for_log(account_id, pending_balance.clone(), db_dir.clone())
+    );
+    let (sender_result, receiver_result) = validate_transaction(
+        storage,
+        index,
+        tx,
+        mediator,
+        pending_balance,
+        tx_id,
+        ValidationOptions {
+            dry_run,
+            ..ValidationOptions::default()
+        },
+    );
+    Ok((tx_id, vec![sender_result, receiver_result]))
+}
+
+fn verify_account_file(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
+    _db_dir: PathBuf,
+    tx: CoreTransaction,
+    dry_run: bool,
+) -> Result<(u32, Vec<ValidationResult>), Error> {
+    let (account_tx, tx_id) = match tx {
+        CoreTransaction::Account {
+            account_tx, tx_id, ..
+        } => (account_tx, tx_id),
+        tx => return Err(Error::TransactionIsNotReadyForValidation { tx }),
+    };
+    let account_id = account_tx.pub_account.enc_asset_id;
+    let result = match AccountIndex::lookup(index, account_id, storage) {
+        // The account map only keeps the last account that was created with an id, so an
+        // account that was created earlier with the same id is a duplicate of it.
+        Ok((user, ticker, map_tx_id)) if map_tx_id != tx_id => Err(Error::DuplicateAccount {
+            account_id: PrintableAccountId(account_id.encode()).to_string(),
+            user,
+            ticker,
+        }),
+        _ => validate_account(storage, index, account_id, dry_run),
+    };
+    match result {
+        Err(error) => {
+            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+            error!("tx-{}: Ignoring the validation error and continuing the with rest of the validations.", tx_id);
+        }
+        Ok(_) => (),
+    };
+    Ok((tx_id, vec![]))
+}
+
+/// Returns the initialization files of the transfers, which hold the sender's ordering state of
+/// the transfers, keyed by tx_id. The files are only parsed, not loaded.
+fn transfer_init_files(tx_files: &[String]) -> HashMap<u32, (String, String, String)> {
+    let init_state = TransferTxState::Initialization(TxSubstate::Started).to_string();
+    tx_files
+        .iter()
+        .filter_map(|tx_file| parse_tx_name(tx_file.clone()).ok())
+        .filter(|(_, _, state, _)| state == &init_state)
+        .map(|(tx_id, user, state, tx_file_path)| (tx_id, (user, state, tx_file_path)))
+        .collect()
+}
+
+/// Returns the ordering state of the account whose balance `tx` decreases. A justified transfer
+/// does not carry the sender's ordering state, so it is loaded from the transfer's
+/// initialization in `init_files`.
+fn sender_ordering_state(
+    storage: &dyn Storage,
+    tx: &CoreTransaction,
+    init_files: &HashMap<u32, (String, String, String)>,
+) -> Result<OrderingState, Error> {
+    let tx_id = match tx {
+        CoreTransaction::TransferJustify { tx_id, .. } => *tx_id,
+        tx => return Ok(tx.ordering_state()),
+    };
+    match init_files.get(&tx_id) {
+        Some((user, state, path)) => {
+            let init = load_tx_file(storage, tx_id, user.clone(), state.clone(), path.clone())?;
+            Ok(init.ordering_state())
+        }
+        None => Ok(OrderingState::new(tx_id)),
+    }
+}
+
+/// Checks that the transaction that the user's account reflected when `tx` was created, as
+/// recorded in its ordering state, is either validated or comes before `tx` in the batch.
+/// Otherwise, `tx` would be validated against a stale account. The ordering state of a transfer
+/// is the sender's, which is loaded from the transfer's initialization in `init_files`.
+fn ensure_predecessor_is_known(
+    storage: &dyn Storage,
+    tx: &CoreTransaction,
+    init_files: &HashMap<u32, (String, String, String)>,
+    last_validated_tx_id: i32,
+    batch_tx_ids: &HashSet<u32>,
+) -> Result<(), Error> {
+    let predecessor =
+        match sender_ordering_state(storage, tx, init_files)?.last_processed_tx_counter {
+            Some(predecessor) => predecessor,
+            None => return Ok(()),
+        };
+    if predecessor as i32 <= last_validated_tx_id || batch_tx_ids.contains(&predecessor) {
+        return Ok(());
+    }
+    Err(Error::MissingPredecessor {
+        tx_id: tx.tx_id().unwrap_or_default(),
+        predecessor,
+    })
+}
+
+/// Checks that both parties of a transfer hold accounts for the same asset. The tickers come from
+/// the account map, which is keyed by the encrypted asset ids of the accounts.
+fn ensure_same_asset(sender_ticker: &str, receiver_ticker: &str) -> Result<(), Error> {
+    if sender_ticker != receiver_ticker {
+        return Err(Error::AssetIdMismatch {
+            sender_ticker: sender_ticker.to_string(),
+            receiver_ticker: receiver_ticker.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the failure to record for the error of `ensure_same_asset`, along with the tickers
+/// of both accounts.
+fn asset_mismatch_failure(error: Error) -> ValidationFailure {
+    match error {
+        Error::AssetIdMismatch {
+            sender_ticker,
+            receiver_ticker,
+        } => ValidationFailure::AssetIdMismatch {
+            sender_ticker,
+            receiver_ticker,
+        },
+        _ => ValidationFailure::Unknown,
+    }
+}
+
+/// Returns true if the mediator of the transfer has recorded a rejection for it, signed with
+/// its on-chain signing key.
+fn is_rejected_by_mediator(storage: &dyn Storage, tx_id: u32, mediator: &str) -> bool {
+    let rejection: Result<MediatorRejection, Error> = storage.get(
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &mediator_rejection_file(tx_id),
+    );
+    let rejection = match rejection {
+        Err(_) => return false,
+        Ok(rejection) => rejection,
+    };
+    if rejection.tx_id != tx_id || rejection.mediator != mediator {
+        return false;
+    }
+    let public_key = storage
+        .get::<Vec<u8>>(ON_CHAIN_DIR, mediator, MEDIATOR_SIGNING_PUBLIC_KEY_FILE)
+        .ok()
+        .and_then(|bytes| PublicKey::from_bytes(&bytes).ok());
+    match public_key {
+        Some(public_key) if rejection.is_signed_by(&public_key) => true,
+        _ => {
+            warn!(
+                "tx-{}: Ignoring the rejection, it is not signed by mediator {}.",
+                tx_id, mediator
+            );
+            false
+        }
+    }
+}
+
+/// Saves the transfer under the rejected state, so that it is not picked up again.
+fn save_rejected_transfer(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
+    tx: &JustifiedTransferTx,
+    tx_id: u32,
+) -> Result<(), Error> {
+    let (sender, _, _) = AccountIndex::lookup(
+        index,
+        tx.finalized_data.init_data.memo.sender_account_id,
+        storage,
+    )?;
+    let state = TransferTxState::Justification(TxSubstate::Rejected);
+    storage.put(
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &confidential_transaction_file(tx_id, &sender, state),
+        &TransferInstruction {
+            data: tx.encode().to_vec(),
+            state,
+        },
+    )
+}
+
+/// Lazily validates the given transactions. Each transaction is only validated once its first
+/// item is pulled from the returned iterator, which lets the caller stop at the first failure.
+/// A transaction yields one item for each of its validation results, e.g. one for the sender
+/// and one for the receiver of a transfer, or a single error if it fails to validate. Unlike
+/// `validate_all_pending`, nothing is written to `storage` and the results are not applied to
+/// the account balances.
+pub fn verify_pipeline<'a>(
+    txs: impl Iterator<Item = CoreTransaction> + 'a,
+    storage: &'a dyn Storage,
+    db_dir: PathBuf,
+) -> impl Iterator<Item = (u32, Result<ValidationResult, Error>)> + 'a {
+    let index = AccountIndex::load(storage);
+    let registry = VerifierRegistry::default();
+    txs.flat_map(move |tx| {
+        let tx_id = tx.tx_id().unwrap_or_default();
+        let results: Vec<(u32, Result<ValidationResult, Error>)> =
+            match registry.verify(storage, Some(&index), db_dir.clone(), tx, true) {
+                Ok((_, results)) => results
+                    .into_iter()
+                    .map(|result| (tx_id, Ok(result)))
+                    .collect(),
+                Err(error) => vec![(tx_id, Err(error))],
+            };
+        results
+    })
+}
+
+/// The outcome of verifying a transaction, along with its tx_id if it could be read.
+type VerifiedTransaction = (Option<u32>, Result<(u32, Vec<ValidationResult>), Error>);
+
+/// The outcome of `validate_and_apply`.
+struct AppliedBatch {
+    /// The largest tx_id among the validated transactions.
+    last_tx_id: Option<u32>,
+    /// The number of validated transactions.
+    validated: usize,
+    skipped: Vec<SkippedTransaction>,
+}
+
+/// Validates the given transactions and applies their results to the account balances.
+///
+/// Account creations and issuances update the accounts that the later transactions are
+/// validated against, so they are validated one at a time, in order. Each run of consecutive
+/// transfers in between is verified concurrently. A transfer only reads the accounts and
+/// writes its own instruction, and each verification creates its own `OsRng`.
+///
+/// `on_progress` is called with the number of verified transactions out of `total` after each
+/// transaction is verified, and then with the number of updated accounts out of the accounts
+/// of the batch after each account is reconciled.
+///
+/// When `options.partial` is set, the transactions that fail to load or to validate are skipped
+/// and returned, instead of failing the whole batch.
+fn validate_and_apply(
+    storage: &dyn Storage,
+    registry: &VerifierRegistry,
+    db_dir: PathBuf,
+    txs: impl Iterator<Item = Result<CoreTransaction, Error>>,
+    total: usize,
+    options: ValidationOptions,
+    on_progress: &mut dyn FnMut(usize, usize),
+) -> Result<AppliedBatch, Error> {
+    // The account map is written when the accounts are created, so it is complete for the batch.
+    let index = AccountIndex::load(storage);
+    let mut validated: Vec<(u32, Vec<ValidationResult>)> = vec![];
+    let mut skipped: Vec<SkippedTransaction> = vec![];
+    let mut transfers: Vec<CoreTransaction> = vec![];
+    let mut processed = 0;
+    // The concurrently verified transfers are reported once the whole run is verified.
+    let mut report = |validated: &mut Vec<(u32, Vec<ValidationResult>)>,
+                      skipped: &mut Vec<SkippedTransaction>,
+                      outcomes: Vec<VerifiedTransaction>|
+     -> Result<(), Error> {
+        for (tx_id, outcome) in outcomes {
+            match outcome {
+                Ok(tx_results) => validated.push(tx_results),
+                Err(error) if options.partial => {
+                    warn!("Skipping the transaction {:?}: {:#?}", tx_id, error);
+                    skipped.push(SkippedTransaction { tx_id, error });
+                }
+                Err(error) => return Err(error),
             }
-            CoreTransaction::Account {
-                account_tx,
-                tx_id,
-                ordering_state: _,
-            } => {
-                match validate_account(db_dir.clone(), account_tx.pub_account.enc_asset_id) {
-                    Err(error) => {
-                        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-                        error!("tx-{}: Ignoring the validation error and continuing the with rest of the validations.", tx_id);
-                    }
-                    Ok(_) => (),
-                };
-                last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
+            processed += 1;
+            on_progress(processed, total);
+        }
+        Ok(())
+    };
+    for tx in txs {
+        let tx = match tx {
+            Ok(tx) => tx,
+            Err(error) => {
+                report(&mut validated, &mut skipped, vec![(None, Err(error))])?;
+                continue;
             }
-            _ => {
-                return Err(Error::TransactionIsNotReadyForValidation { tx });
+        };
+        if let CoreTransaction::TransferJustify { .. } = tx {
+            transfers.push(tx);
+            continue;
+        }
+        let verified =
+            validate_concurrently(storage, registry, Some(&index), db_dir.clone(), transfers);
+        report(&mut validated, &mut skipped, verified)?;
+        transfers = vec![];
+        let tx_id = tx.tx_id();
+        let verified = validate_with_retries(storage, registry, Some(&index), db_dir.clone(), tx);
+        report(&mut validated, &mut skipped, vec![(tx_id, verified)])?;
+    }
+    let verified =
+        validate_concurrently(storage, registry, Some(&index), db_dir.clone(), transfers);
+    report(&mut validated, &mut skipped, verified)?;
+
+    // Computed once all the validations are done, so it does not depend on the order in which
+    // the concurrent validations complete.
+    let last_tx_id = validated.iter().map(|(tx_id, _)| *tx_id).max();
+    let validated_count = validated.len();
+    let results: Vec<(u32, ValidationResult)> = validated
+        .into_iter()
+        .flat_map(|(tx_id, tx_results)| tx_results.into_iter().map(move |result| (tx_id, result)))
+        .collect();
+
+    apply_validation_results(
+        storage,
+        db_dir,
+        results,
+        last_tx_id,
+        options.strategy,
+        options.lock_mode,
+        on_progress,
+    )?;
+    Ok(AppliedBatch {
+        last_tx_id,
+        validated: validated_count,
+        skipped,
+    })
+}
+
+/// Validates the transactions on a rayon thread pool. The outcomes are in the same order as
+/// `txs`, each along with the tx_id of its transaction.
+fn validate_concurrently(
+    storage: &dyn Storage,
+    registry: &VerifierRegistry,
+    index: Option<&AccountIndex>,
+    db_dir: PathBuf,
+    txs: Vec<CoreTransaction>,
+) -> Vec<VerifiedTransaction> {
+    txs.into_par_iter()
+        .map(|tx| {
+            let tx_id = tx.tx_id();
+            let outcome = validate_with_retries(storage, registry, index, db_dir.clone(), tx);
+            (tx_id, outcome)
+        })
+        .collect()
+}
+
+/// Validates the transaction, and retries it with an exponential backoff if it fails with a
+/// transient error and its verifier is retriable. The last error is returned once the retries
+/// are used up.
+fn validate_with_retries(
+    storage: &dyn Storage,
+    registry: &VerifierRegistry,
+    index: Option<&AccountIndex>,
+    db_dir: PathBuf,
+    tx: CoreTransaction,
+) -> Result<(u32, Vec<ValidationResult>), Error> {
+    let mut backoff = TRANSIENT_ERROR_BACKOFF;
+    let retries = if registry.is_retriable(&tx) {
+        TRANSIENT_ERROR_RETRIES
+    } else {
+        0
+    };
+    for _ in 0..retries {
+        match registry.verify(storage, index, db_dir.clone(), tx.clone(), false) {
+            Err(error) if error.is_transient() => {
+                warn!(
+                    "tx-{}: Retrying in {:?} after a transient error: {:#?}",
+                    tx.tx_id().unwrap_or_default(),
+                    backoff,
+                    error
+                );
+                std::thread::sleep(backoff);
+                backoff *= 2;
             }
+            result => return result,
         }
     }
+    registry.verify(storage, index, db_dir, tx, false)
+}
+
+/// Validates and applies the transactions that were added since the last run. Only the
+/// transaction files whose tx_id, taken from the file name, is greater than the one in the
+/// `LAST_VALIDATED_TX_ID_FILE` are loaded, so a validator that runs continuously can call it
+/// on every new transaction.
+pub fn validate_all_pending(
+    db_dir: PathBuf,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+) -> Result<(), Error> {
+    validate_all_pending_with_storage(
+        &FileStorage::new(db_dir.clone()),
+        db_dir,
+        strategy,
+        lock_mode,
+    )
+}
+
+/// Same as `validate_all_pending`, but reports the progress of the validation to
+/// `on_progress`. The callback receives `(processed, total)` after each transaction is verified,
+/// where `total` is the number of transactions that are ready for validation. During the
+/// reconciliation of the balances, it receives the number of updated accounts out of the
+/// accounts that are touched by the batch.
+pub fn validate_all_pending_with_progress(
+    db_dir: PathBuf,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<(), Error> {
+    validate_pending(
+        &FileStorage::new(db_dir.clone()),
+        &VerifierRegistry::default(),
+        db_dir,
+        ValidationOptions {
+            strategy,
+            lock_mode,
+
+            ..ValidationOptions::default()
+        },
+        &mut on_progress,
+    )
+    .map(|_| ())
+}
+
+/// Same as `validate_all_pending`, but the ledger is read from and written to `storage`. The
+/// `db_dir` is only used to decrypt the amounts for the debug log and to append to the audit log.
+pub fn validate_all_pending_with_storage(
+    storage: &dyn Storage,
+    db_dir: PathBuf,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+) -> Result<(), Error> {
+    validate_pending(
+        storage,
+        &VerifierRegistry::default(),
+        db_dir,
+        ValidationOptions {
+            strategy,
+            lock_mode,
+
+            ..ValidationOptions::default()
+        },
+        &mut |_, _| {},
+    )
+    .map(|_| ())
+}
+
+/// Same as `validate_all_pending`, but each transaction is validated by the verifier that is
+/// registered for its kind in `registry`.
+pub fn validate_all_pending_with_registry(
+    registry: &VerifierRegistry,
+    db_dir: PathBuf,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+) -> Result<(), Error> {
+    validate_pending(
+        &FileStorage::new(db_dir.clone()),
+        registry,
+        db_dir,
+        ValidationOptions {
+            strategy,
+            lock_mode,
+
+            ..ValidationOptions::default()
+        },
+        &mut |_, _| {},
+    )
+    .map(|_| ())
+}
+
+/// Validates and applies the pending transactions. Returns the number of validated
+/// transactions, and the skipped ones if `options.partial` is set.
+fn validate_pending(
+    storage: &dyn Storage,
+    registry: &VerifierRegistry,
+    db_dir: PathBuf,
+    options: ValidationOptions,
+    on_progress: &mut dyn FnMut(usize, usize),
+) -> Result<(usize, Vec<SkippedTransaction>), Error> {
+    // Held until the batch is fully applied, so that a concurrent validator does not pick up
+    // the same pending transactions.
+    let _checkpoint_lock = lock_checkpoint(storage, options.lock_mode)?;
+
+    // TODO: This function should be called when any justify is called. To be fixed in CRYP-131.
+    // The total is counted from the file names, so that the transactions are still loaded lazily.
+    // It is only used to report the progress, and the validated transactions are counted as
+    // they are validated.
+    let tx_files = all_unverified_tx_files(storage)?;
+    let total = tx_files
+        .iter()
+        .filter(|tx_file| is_ready_tx_file(tx_file))
+        .count();
+    if total == 0 {
+        // There is nothing to validate, and the `LAST_VALIDATED_TX_ID_FILE` is left as is.
+        remove_validation_checkpoint(storage)?;
+        return Ok((0, vec![]));
+    }
+    let last_validated_tx_id = last_verified_tx_id(storage);
+    let init_files = transfer_init_files(&tx_files);
+    let mut batch_tx_ids = HashSet::new();
+    let txs = ready_iter_from(storage, tx_files).map(move |tx| {
+        let tx = tx?;
+        ensure_predecessor_is_known(
+            storage,
+            &tx,
+            &init_files,
+            last_validated_tx_id,
+            &batch_tx_ids,
+        )?;
+        batch_tx_ids.extend(tx.tx_id());
+        Ok(tx)
+    });
+    let batch = validate_and_apply(
+        storage,
+        registry,
+        db_dir.clone(),
+        txs,
+        total,
+        options,
+        on_progress,
+    )?;
+
+    // All the accounts of this batch are saved. If the process is interrupted after this
+    // point, the stale checkpoint's last_tx_id will not match the next batch and is ignored.
+    // An empty batch keeps the last validated tx_id of the previous batch.
+    if batch.last_tx_id.is_some() {
+        storage.put_json(
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            LAST_VALIDATED_TX_ID_FILE,
+            &batch.last_tx_id,
+        )?;
+    }
+    remove_validation_checkpoint(storage)?;
+    Ok((batch.validated, batch.skipped))
+}
+
+/// Same as `validate_all_pending`, for a validator that runs continuously, but returns the
+/// number of transactions that were validated since the last run.
+pub fn validate_incremental(db_dir: PathBuf) -> Result<usize, Error> {
+    validate_pending(
+        &FileStorage::new(db_dir.clone()),
+        &VerifierRegistry::default(),
+        db_dir,
+        ValidationOptions::default(),
+        &mut |_, _| {},
+    )
+    .map(|(validated, _)| validated)
+}
+
+/// Same as `validate_all_pending`, but the transactions that fail to load or to validate do
+/// not fail the batch. The rest of the batch is validated and applied, and the skipped
+/// transactions are returned. Since the last validated tx_id moves past them, the skipped
+/// transactions are not picked up again by the next pass.
+pub fn validate_all_pending_partial(
+    db_dir: PathBuf,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+) -> Result<Vec<SkippedTransaction>, Error> {
+    validate_pending(
+        &FileStorage::new(db_dir.clone()),
+        &VerifierRegistry::default(),
+        db_dir,
+        ValidationOptions {
+            strategy,
+            lock_mode,
+            partial: true,
+            ..ValidationOptions::default()
+        },
+        &mut |_, _| {},
+    )
+    .map(|(_, skipped)| skipped)
+}
 
-    // TODO: CRYP-134, use a more elegant way of writing the following code.
+/// Runs `validate_all_pending` `passes` times, and then reports the accounts that still have
+/// transactions that are not ready for validation, along with the ids of those transactions.
+/// Each entry is `(user, ticker, blocking tx_ids)`, and the result is sorted by user and ticker.
+pub fn stuck_accounts(
+    db_dir: PathBuf,
+    passes: u32,
+) -> Result<Vec<(String, String, Vec<u32>)>, Error> {
+    for _ in 0..passes {
+        validate_all_pending(
+            db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )?;
+    }
 
-    // find all users
-    let mut users: Vec<String> = vec![];
-    for result in results.clone() {
-        if result.user != "n/a" {
-            users.push(result.user);
+    let storage: &dyn Storage = &FileStorage::new(db_dir);
+    let all_txs = load_all_unverified_txs(storage)?;
+    let mut stuck = vec![];
+    for (account_id, (user, ticker, _)) in load_account_map(storage) {
+        let encoded_account_id =
+            base64::decode(&account_id).map_err(|error| Error::AccountIdDecodeError {
+                account_id: account_id.clone(),
+                error,
+            })?;
+        let blocking = blocking_tx_ids(&encoded_account_id, &all_txs);
+        if !blocking.is_empty() {
+            stuck.push((user, ticker, blocking));
         }
     }
-    // find all accounts
-    let mut accounts: HashSet<(String, String)> = HashSet::new();
-    for user in users {
-        for result in results.clone() {
-            if result.user == user {
-                accounts.insert((result.user, result.ticker));
+    stuck.sort();
+    Ok(stuck)
+}
+
+/// Runs the same validations as `validate_all_pending`, but instead of updating the account
+/// balances and the `LAST_VALIDATED_TX_ID_FILE`, reports the projected change of each account.
+/// Since no account is saved, transactions on accounts that are created in the same batch
+/// will fail to validate. The transactions that fail to load or to validate are returned along
+/// with the reports, instead of failing the whole run.
+pub fn validate_all_pending_dry_run(
+    db_dir: PathBuf,
+) -> Result<(Vec<ValidationReport>, Vec<SkippedTransaction>), Error> {
+    let storage: &dyn Storage = &FileStorage::new(db_dir.clone());
+    let all_unverified_and_ready = unverified_ready_iter(storage)?;
+    let index = AccountIndex::load(storage);
+    let registry = VerifierRegistry::default();
+
+    let mut results: Vec<(u32, ValidationResult)> = vec![];
+    let mut failed: Vec<SkippedTransaction> = vec![];
+    for tx in all_unverified_and_ready {
+        let tx_id = tx.as_ref().ok().and_then(|tx| tx.tx_id());
+        match tx.and_then(|tx| registry.verify(storage, Some(&index), db_dir.clone(), tx, true)) {
+            Ok((tx_id, tx_results)) => {
+                results.extend(tx_results.into_iter().map(|result| (tx_id, result)))
             }
+            Err(error) => failed.push(SkippedTransaction { tx_id, error }),
         }
     }
 
-    for (user, ticker) in accounts.clone() {
-        let ordered_pub_account: OrderedPubAccount = load_object(
+    let mut reports = vec![];
+    for ((user, ticker), account_results) in group_results_by_account(&results) {
+        let ordered_pub_account: OrderedPubAccount =
+            storage.get(ON_CHAIN_DIR, &user, &user_public_account_file(&ticker))?;
+        let old_balance: EncryptedAmount = storage.get(
+            ON_CHAIN_DIR,
+            &user,
+            &user_public_account_balance_file(&ticker),
+        )?;
+        let account_id = ordered_pub_account.pub_account.enc_asset_id;
+        let new_balance: EncryptedAmount = fold_account_results(
+            old_balance.clone().into(),
+            &account_results,
+            account_id,
             db_dir.clone(),
+        )
+        .into();
+        reports.push(ValidationReport {
+            old_balance: debug_decrypt(account_id, old_balance.clone(), db_dir.clone()).ok(),
+            new_balance: debug_decrypt(account_id, new_balance.clone(), db_dir.clone()).ok(),
+            old_enc_balance: old_balance,
+            new_enc_balance: new_balance,
+            tx_ids: account_results
+                .iter()
+                .filter(|(_, result)| result.amount.is_some())
+                .map(|(tx_id, _)| *tx_id)
+                .collect(),
+            user,
+            ticker,
+        });
+    }
+    reports.sort_by(|a, b| (&a.user, &a.ticker).cmp(&(&b.user, &b.ticker)));
+    Ok((reports, failed))
+}
+
+/// Validates only the transactions whose tx_id is in the inclusive range `[from, to]`, e.g. after
+/// their data was repaired, and applies their results to the account balances. The
+/// transactions in the range are loaded whether or not they are validated already, but the
+/// amounts that the accounts already reflect are not applied again. The
+/// `LAST_VALIDATED_TX_ID_FILE` is neither read nor written. The updated accounts are stamped
+/// with the last tx_id of the range, so the pending transactions of these accounts before
+/// `from` should be validated first.
+pub fn validate_range(
+    db_dir: PathBuf,
+    from: u32,
+    to: u32,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+) -> Result<(), Error> {
+    validate_range_with_storage(
+        &FileStorage::new(db_dir.clone()),
+        db_dir,
+        from,
+        to,
+        strategy,
+        lock_mode,
+    )
+}
+
+/// Same as `validate_range`, but the accounts and transactions are loaded from and saved to
+/// `storage`.
+pub fn validate_range_with_storage(
+    storage: &dyn Storage,
+    db_dir: PathBuf,
+    from: u32,
+    to: u32,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+) -> Result<(), Error> {
+    let _checkpoint_lock = lock_checkpoint(storage, lock_mode)?;
+    let txs_in_range = load_ready_in_range(storage, from, to)?;
+    let total = txs_in_range.len();
+    validate_and_apply(
+        storage,
+        &VerifierRegistry::default(),
+        db_dir,
+        txs_in_range.into_iter().map(Ok),
+        total,
+        ValidationOptions {
+            strategy,
+            lock_mode,
+            ..ValidationOptions::default()
+        },
+        &mut |_, _| {},
+    )?;
+    remove_validation_checkpoint(storage)
+}
+
+/// Undoes the validation of all the transactions whose tx_id is greater than `to_tx_id`, e.g.
+/// after a chain reorg. This is the inverse of the reconciliation in `validate_all_pending`:
+/// the amounts of the validated transfers and issuances are reverted, the accounts that were
+/// created after `to_tx_id` are removed, and the validated and rejected copies of the
+/// instructions are deleted, so that the transactions are validated again in the next pass.
+/// Finally, `to_tx_id` is saved as the last validated transaction.
+pub fn rewind_validation(db_dir: PathBuf, to_tx_id: u32) -> Result<(), Error> {
+    let storage: &dyn Storage = &FileStorage::new(db_dir.clone());
+    let _checkpoint_lock = lock_checkpoint(storage, LockMode::Wait)?;
+    let last_tx_id = last_verified_tx_id(storage);
+    if last_tx_id <= to_tx_id as i32 {
+        return Ok(());
+    }
+    let index = AccountIndex::load(storage);
+
+    // All the files of the range are read before anything is changed, so that a file that can
+    // not be reverted fails the rewind before any balance is reverted.
+    // The directions of the reverted results are the opposite of the validated ones.
+    let mut reverted: Vec<(u32, ValidationResult)> = vec![];
+    let mut created_accounts: Vec<(String, String)> = vec![];
+    // The (owner, name) of the on-chain files to remove.
+    let mut processed_files: Vec<(String, String)> = vec![];
+    for tx_file in tx_files_in_range(storage, to_tx_id + 1, last_tx_id as u32)? {
+        let (tx_id, user, state, tx_file) = parse_tx_name(tx_file)?;
+        if state == TransferTxState::Justification(TxSubstate::Validated).to_string() {
+            let instruction: TransferInstruction =
+                storage.get(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &tx_file)?;
+            let tx = JustifiedTransferTx::decode(&mut &instruction.data[..])
+                .map_err(|_| Error::DecodeError)?;
+            let memo = tx.finalized_data.init_data.memo;
+            let (sender, ticker, _) = index.get(memo.sender_account_id)?;
+            let (receiver, _, _) = index.get(memo.receiver_account_id)?;
+            reverted.push((
+                tx_id,
+                reverted_result(
+                    sender,
+                    &ticker,
+                    Direction::Incoming,
+                    memo.enc_amount_using_sender,
+                ),
+            ));
+            reverted.push((
+                tx_id,
+                reverted_result(
+                    receiver,
+                    &ticker,
+                    Direction::Outgoing,
+                    memo.enc_amount_using_receiver,
+                ),
+            ));
+            processed_files.push((COMMON_OBJECTS_DIR.to_string(), tx_file));
+        } else if is_processed_tx_file(&tx_file) {
+            processed_files.push((COMMON_OBJECTS_DIR.to_string(), tx_file));
+        } else if let Some(ticker) = state.strip_prefix("ticker#") {
+            created_accounts.push((user, ticker.to_string()));
+        } else {
+            let validated_state = AssetTxState::Justification(TxSubstate::Validated);
+            let validated_file = asset_transaction_file(tx_id, &user, validated_state);
+            let is_validated_issuance = storage
+                .get_bytes(ON_CHAIN_DIR, &user, &validated_file)
+                .is_ok();
+            let issue_tx = match load_tx_file(storage, tx_id, user.clone(), state, tx_file) {
+                Ok(CoreTransaction::IssueInit { issue_tx, .. }) => issue_tx,
+                Ok(_) => continue,
+                // Only a validated issuance has an amount to revert.
+                Err(error) if !is_validated_issuance => {
+                    warn!(
+                        "Skipping the transaction {} in the rewind: {:#?}",
+                        tx_id, error
+                    );
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            if is_validated_issuance {
+                let (_, ticker, _) = index.get(issue_tx.account_id)?;
+                let amount = issue_tx.memo.enc_issued_amount;
+                reverted.push((
+                    tx_id,
+                    reverted_result(user.clone(), &ticker, Direction::Outgoing, amount),
+                ));
+                processed_files.push((user, validated_file));
+            }
+        }
+    }
+
+    for ((user, ticker), account_results) in group_results_by_account(&reverted) {
+        if created_accounts.contains(&(user.clone(), ticker.clone())) {
+            continue;
+        }
+        let ordered_pub_account: OrderedPubAccount =
+            storage.get(ON_CHAIN_DIR, &user, &user_public_account_file(&ticker))?;
+        let balance: EncryptedAmount = storage.get(
+            ON_CHAIN_DIR,
+            &user,
+            &user_public_account_balance_file(&ticker),
+        )?;
+        let account_id = ordered_pub_account.pub_account.enc_asset_id;
+        let new_balance: EncryptedAmount =
+            fold_account_results(balance.into(), &account_results, account_id, db_dir.clone())
+                .into();
+        storage.put(
             ON_CHAIN_DIR,
             &user,
             &user_public_account_file(&ticker),
+            &OrderedPubAccount {
+                last_processed_tx_counter: std::cmp::min(
+                    ordered_pub_account.last_processed_tx_counter,
+                    Some(to_tx_id),
+                ),
+                pub_account: ordered_pub_account.pub_account,
+            },
         )?;
-        let mut new_balance: EncryptedAmount = load_object(
-            db_dir.clone(),
+        storage.put(
+            ON_CHAIN_DIR,
+            &user,
+            &user_public_account_balance_file(&ticker),
+            &new_balance,
+        )?;
+    }
+
+    for (user, ticker) in created_accounts {
+        for file_name in &[
+            user_public_account_file(&ticker),
+            user_public_account_balance_file(&ticker),
+        ] {
+            if storage.get_bytes(ON_CHAIN_DIR, &user, file_name).is_ok() {
+                storage.remove(ON_CHAIN_DIR, &user, file_name)?;
+            }
+        }
+    }
+    for (owner, processed_file) in processed_files {
+        storage.remove(ON_CHAIN_DIR, &owner, &processed_file)?;
+    }
+
+    storage.put_json(
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        LAST_VALIDATED_TX_ID_FILE,
+        &Some(to_tx_id),
+    )?;
+    remove_validation_checkpoint(storage)
+}
+
+/// Creates the result that reverts a validated amount, with the opposite direction.
+fn reverted_result(
+    user: String,
+    ticker: &str,
+    direction: Direction,
+    amount: EncryptedAmount,
+) -> ValidationResult {
+    ValidationResult {
+        user,
+        ticker: ticker.to_string(),
+        direction,
+        amount: Some(amount.into()),
+        failure: None,
+        persisted: false,
+    }
+}
+
+/// Locks the `VALIDATION_CHECKPOINT_FILE`. Only one validator at a time can apply a batch.
+fn lock_checkpoint(storage: &dyn Storage, lock_mode: LockMode) -> Result<StorageLock, Error> {
+    storage.lock(
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        VALIDATION_CHECKPOINT_FILE,
+        lock_mode,
+    )
+}
+
+/// Loads the checkpoint of an interrupted batch. A checkpoint that belongs to a different
+/// batch, or a missing one, results in an empty checkpoint for the current batch.
+fn load_validation_checkpoint(
+    storage: &dyn Storage,
+    last_tx_id: Option<u32>,
+) -> ValidationCheckpoint {
+    let checkpoint: Result<ValidationCheckpoint, Error> = storage.get_json(
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        VALIDATION_CHECKPOINT_FILE,
+    );
+    match checkpoint {
+        Ok(checkpoint) if checkpoint.last_tx_id == last_tx_id => checkpoint,
+        _ => ValidationCheckpoint {
+            last_tx_id,
+            saved_accounts: vec![],
+        },
+    }
+}
+
+/// Removes the checkpoint once a batch is fully applied.
+fn remove_validation_checkpoint(storage: &dyn Storage) -> Result<(), Error> {
+    let checkpoint = storage.get_bytes(
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        VALIDATION_CHECKPOINT_FILE,
+    );
+    if checkpoint.is_ok() {
+        storage.remove(
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            VALIDATION_CHECKPOINT_FILE,
+        )?;
+    }
+    Ok(())
+}
+
+/// Updates the account balances based on the validation results, and stamps each updated
+/// account with `last_tx_id` as its last processed transaction. The accounts with failed
+/// results are handled according to `strategy`. Each saved account is recorded in the
+/// `VALIDATION_CHECKPOINT_FILE`, so that a restarted validator skips the accounts which
+/// were already saved before it was interrupted. Each account is locked while it is updated.
+/// `on_progress` receives the number of reconciled accounts out of the accounts of the batch.
+fn apply_validation_results(
+    storage: &dyn Storage,
+    db_dir: PathBuf,
+    results: Vec<(u32, ValidationResult)>,
+    last_tx_id: Option<u32>,
+    strategy: ReconciliationStrategy,
+    lock_mode: LockMode,
+    on_progress: &mut dyn FnMut(usize, usize),
+) -> Result<(), Error> {
+    let results_by_account = group_results_by_account(&results);
+    let total_accounts = results_by_account.len();
+
+    let mut failed_accounts: Vec<(String, String)> = results_by_account
+        .iter()
+        .filter(|(_, results)| results.iter().any(|(_, result)| result.amount.is_none()))
+        .map(|(account, _)| account.clone())
+        .collect();
+    failed_accounts.sort();
+    if strategy == ReconciliationStrategy::HaltOnFailure && !failed_accounts.is_empty() {
+        return Err(Error::ReconciliationHalted {
+            accounts: failed_accounts,
+        });
+    }
+
+    let mut checkpoint = load_validation_checkpoint(storage, last_tx_id);
+    for (reconciled, ((user, ticker), account_results)) in
+        results_by_account.into_iter().enumerate()
+    {
+        on_progress(reconciled, total_accounts);
+        let account = (user.clone(), ticker.clone());
+        if checkpoint.saved_accounts.contains(&account) {
+            info!(
+                "Skipping {}-{}, since it was saved before the validation was interrupted.",
+                &user, &ticker
+            );
+            continue;
+        }
+        if strategy == ReconciliationStrategy::RevertUser
+            && failed_accounts
+                .iter()
+                .any(|(failed_user, _)| failed_user == &user)
+        {
+            info!(
+                "Leaving {}-{} untouched, since the user has failed transactions.",
+                &user, &ticker
+            );
+            continue;
+        }
+        let _account_lock = storage.lock(
+            OFF_CHAIN_DIR,
+            &user,
+            &user_public_account_file(&ticker),
+            lock_mode,
+        )?;
+        let ordered_pub_account: OrderedPubAccount =
+            storage.get(ON_CHAIN_DIR, &user, &user_public_account_file(&ticker))?;
+        let balance: EncryptedAmount = storage.get(
             ON_CHAIN_DIR,
             &user,
             &user_public_account_balance_file(&ticker),
@@ -153,99 +1304,159 @@ pub fn validate_all_pending(db_dir: PathBuf) -> Result<(), Error> {
             "------------> Validation complete, updating {}-{}. Starting balance: {}",
             &user,
             &ticker,
-            debug_decrypt(
+            debug_decrypt_for_log(
                 ordered_pub_account.pub_account.enc_asset_id,
-                new_balance.clone(),
+                balance.clone(),
                 db_dir.clone()
-            )?
+            )
         );
-        for result in results.clone() {
-            if result.user == user && result.ticker == ticker {
-                match result.direction {
-                    Direction::Incoming => {
-                        if let Some(amount) = result.amount {
-                            debug!(
-                                "---------------------> updating {}-{} increasing by {}",
-                                &user,
-                                &ticker,
-                                debug_decrypt(
-                                    ordered_pub_account.pub_account.enc_asset_id,
-                                    amount.clone(),
-                                    db_dir.clone()
-                                )?
-                            );
-                            new_balance += amount.clone();
-                        } else {
-                            // based on the reason and the strategy, we can break the loop or ignore
-                            // TODO: add strategy selection to the config. CRYP-132
-                        }
-                    }
-                    Direction::Outgoing => {
-                        if let Some(amount) = result.amount {
-                            debug!(
-                                "---------------------> updating {}-{} decreasing by {}",
-                                &user,
-                                &ticker,
-                                debug_decrypt(
-                                    ordered_pub_account.pub_account.enc_asset_id,
-                                    amount.clone(),
-                                    db_dir.clone()
-                                )?
-                            );
-                            new_balance -= amount.clone();
-                        } else {
-                            // based on the reason and the strategy, we can break the loop or ignore
-                        }
-                    }
-                }
-            }
-        }
-
-        save_object(
+        #[cfg(feature = "audit")]
+        let old_balance = debug_decrypt(
+            ordered_pub_account.pub_account.enc_asset_id,
+            balance.clone(),
+            db_dir.clone(),
+        )
+        .ok();
+        let new_balance: EncryptedAmount = fold_account_results(
+            balance.into(),
+            &account_results,
+            ordered_pub_account.pub_account.enc_asset_id,
             db_dir.clone(),
+        )
+        .into();
+
+        // The account is saved after its balance, since its last processed transaction marks the
+        // results as applied to the balance.
+        storage.put(
+            ON_CHAIN_DIR,
+            &user,
+            &user_public_account_balance_file(&ticker),
+            &new_balance,
+        )?;
+        storage.put(
             ON_CHAIN_DIR,
             &user,
             &user_public_account_file(&ticker),
             &OrderedPubAccount {
-                last_processed_tx_counter: last_tx_id,
+                // A range of transactions before the account's last processed transaction
+                // does not move it back.
+                last_processed_tx_counter: std::cmp::max(
+                    ordered_pub_account.last_processed_tx_counter,
+                    last_tx_id,
+                ),
                 pub_account: PubAccount {
                     enc_asset_id: ordered_pub_account.pub_account.enc_asset_id,
                     owner_enc_pub_key: ordered_pub_account.pub_account.owner_enc_pub_key,
                 },
             },
         )?;
-        save_object(
+
+        #[cfg(feature = "audit")]
+        append_to_audit_log(
             db_dir.clone(),
-            ON_CHAIN_DIR,
-            &user,
-            &user_public_account_balance_file(&ticker),
-            &new_balance,
+            &BalanceMutation {
+                user: user.clone(),
+                ticker: ticker.clone(),
+                applied: account_results
+                    .iter()
+                    .filter(|(_, result)| !result.persisted && result.amount.is_some())
+                    .map(|(tx_id, result)| (*tx_id, result.direction.clone()))
+                    .collect(),
+                old_balance,
+                new_balance: debug_decrypt(
+                    ordered_pub_account.pub_account.enc_asset_id,
+                    new_balance,
+                    db_dir.clone(),
+                )
+                .ok(),
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or_default(),
+            },
+        )?;
+
+        checkpoint.saved_accounts.push(account);
+        storage.put_json(
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            VALIDATION_CHECKPOINT_FILE,
+            &checkpoint,
         )?;
     }
+    on_progress(total_accounts, total_accounts);
 
-    save_to_file(
-        db_dir,
-        OFF_CHAIN_DIR,
-        COMMON_OBJECTS_DIR,
-        LAST_VALIDATED_TX_ID_FILE,
-        &last_tx_id,
-    )?;
     Ok(())
 }
 
-pub fn validate_asset_issuance(
+/// Groups the validation results by (user, ticker) in a single pass, keeping their original order.
+fn group_results_by_account(
+    results: &[(u32, ValidationResult)],
+) -> HashMap<(String, String), Vec<(u32, &ValidationResult)>> {
+    let mut results_by_account: HashMap<(String, String), Vec<(u32, &ValidationResult)>> =
+        HashMap::new();
+    for (tx_id, result) in results.iter().filter(|(_, result)| result.user != "n/a") {
+        results_by_account
+            .entry((result.user.clone(), result.ticker.clone()))
+            .or_default()
+            .push((*tx_id, result));
+    }
+    results_by_account
+}
+
+/// Applies the amounts of an account's validation results to its balance. The failed results
+/// have no amount and are skipped.
+fn fold_account_results(
+    balance: EncryptedBalance,
+    account_results: &[(u32, &ValidationResult)],
+    account_id: EncryptedAssetId,
     db_dir: PathBuf,
+) -> EncryptedBalance {
+    let mut new_balance = balance;
+    for (_, result) in account_results {
+        if result.persisted {
+            continue;
+        }
+        if let Some(amount) = &result.amount {
+            let change = match result.direction {
+                Direction::Incoming => "increasing",
+                Direction::Outgoing => "decreasing",
+            };
+            debug!(
+                "---------------------> updating {}-{} {} by {}",
+                &result.user,
+                &result.ticker,
+                change,
+                debug_decrypt_for_log(account_id, amount.clone().into(), db_dir.clone())
+            );
+            match result.direction {
+                Direction::Incoming => new_balance.apply_incoming(amount),
+                Direction::Outgoing => new_balance.apply_outgoing(amount),
+            }
+        }
+    }
+    new_balance
+}
+
+/// Validates an asset issuance and, unless `options.dry_run` is set, saves its instruction as
+/// validated. The issued amount is returned to be added to the issuer's balance when the batch
+/// is reconciled. The validated instruction is only saved if it is not already on file, unless
+/// `options.force_rewrite` is set.
+pub fn validate_asset_issuance(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
     amount: u32,
     asset_tx: InitializedAssetTx,
     tx_id: u32,
+    options: ValidationOptions,
 ) -> ValidationResult {
     let load_objects_timer = Instant::now();
 
     let issuer_account_id = asset_tx.account_id;
-    let res = get_user_ticker_from(issuer_account_id, db_dir.clone());
+    let res = AccountIndex::lookup(index, issuer_account_id, storage);
     if let Err(error) = res {
         error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return ValidationResult::error("n/a", "n/a");
+        return ValidationResult::failed("n/a", "n/a", lookup_failure(&error));
     }
     let (issuer, ticker, _) = res.unwrap();
     info!(
@@ -253,20 +1464,15 @@ pub fn validate_asset_issuance(
         tx_id, issuer, ticker,
     );
 
-    let issuer_ordered_pub_account: Result<OrderedPubAccount, Error> = load_object(
-        db_dir.clone(),
-        ON_CHAIN_DIR,
-        &issuer,
-        &user_public_account_file(&ticker),
-    );
+    let issuer_ordered_pub_account: Result<OrderedPubAccount, Error> =
+        storage.get(ON_CHAIN_DIR, &issuer, &user_public_account_file(&ticker));
     if let Err(error) = issuer_ordered_pub_account {
         error!("Error in validation of tx-{}: {:#?}", tx_id, error);
         return ValidationResult::error(&issuer, &ticker);
     }
     let issuer_ordered_pub_account = issuer_ordered_pub_account.unwrap();
 
-    let issuer_account_balance: Result<EncryptedAmount, Error> = load_object(
-        db_dir.clone(),
+    let issuer_account_balance: Result<EncryptedAmount, Error> = storage.get(
         ON_CHAIN_DIR,
         &issuer,
         &user_public_account_balance_file(&ticker),
@@ -286,9 +1492,10 @@ pub fn validate_asset_issuance(
 
     let validate_issuance_transaction_timer = Instant::now();
 
+    // The new balance is not saved here. The issued amount is added to the balance along with
+    // the other results of the account, when the batch is reconciled.
     let validator = AssetValidator;
-    // TODO: CRYP-165: This requires more work to handle properly. At the moment, I am ignoring the the balance returned.
-    let _ = match validator
+    if let Err(error) = validator
         .verify_asset_transaction(
             amount,
             &asset_tx,
@@ -296,14 +1503,11 @@ pub fn validate_asset_issuance(
             &issuer_account_balance,
             &[],
         )
-        .map_err(|error| Error::LibraryError { error })
+        .map_err(Error::from)
     {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return ValidationResult::error(&issuer, &ticker);
-        }
-        Ok(pub_account) => pub_account,
-    };
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return ValidationResult::error(&issuer, &ticker);
+    }
 
     timing!(
         "validator.issuance.transaction",
@@ -319,15 +1523,20 @@ pub fn validate_asset_issuance(
         state: new_state,
         data: asset_tx.encode().to_vec(),
     };
-    if let Err(error) = save_object(
-        db_dir.clone(),
-        ON_CHAIN_DIR,
-        &issuer,
-        &asset_transaction_file(tx_id, &issuer, new_state),
-        &instruction,
-    ) {
-        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return ValidationResult::error(&issuer, &ticker);
+    if !options.dry_run {
+        let validated_file = asset_transaction_file(tx_id, &issuer, new_state);
+        // A re-run finds the instruction that it would save already validated on file.
+        let already_validated =
+            match storage.get::<AssetInstruction>(ON_CHAIN_DIR, &issuer, &validated_file) {
+                Err(_) => false,
+                Ok(saved) => saved.state.to_string() == new_state.to_string(),
+            };
+        if !already_validated || options.force_rewrite {
+            if let Err(error) = storage.put(ON_CHAIN_DIR, &issuer, &validated_file, &instruction) {
+                error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+                return ValidationResult::error(&issuer, &ticker);
+            }
+        }
     }
 
     timing!(
@@ -340,16 +1549,55 @@ pub fn validate_asset_issuance(
     ValidationResult {
         user: issuer,
         ticker,
-        amount: Some(asset_tx.memo.enc_issued_amount),
+        amount: Some(asset_tx.memo.enc_issued_amount.into()),
         direction: Direction::Incoming,
+        failure: None,
+        persisted: is_applied(&issuer_ordered_pub_account, tx_id),
     }
 }
 
-pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result<(), Error> {
+/// Validates the account creation transaction of `account_id` and, unless `dry_run` is set,
+/// saves the account as validated.
+pub fn validate_account(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
+    account_id: EncryptedAssetId,
+    dry_run: bool,
+) -> Result<(), Error> {
+    validate_accounts(storage, index, &[account_id], dry_run)?
+        .pop()
+        .expect("There is one result for each account.")
+}
+
+/// Validates the account creation transactions of `account_ids` in order, and returns the
+/// result of each account. The list of valid asset ids is loaded once for all the accounts, and
+/// an error is only returned if it can not be loaded.
+pub fn validate_accounts(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
+    account_ids: &[EncryptedAssetId],
+    dry_run: bool,
+) -> Result<Vec<Result<(), Error>>, Error> {
+    let valid_asset_ids = get_asset_ids(storage)?;
+    Ok(account_ids
+        .iter()
+        .map(|account_id| {
+            validate_account_with(storage, index, *account_id, &valid_asset_ids, dry_run)
+        })
+        .collect())
+}
+
+fn validate_account_with(
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
+    account_id: EncryptedAssetId,
+    valid_asset_ids: &[Scalar],
+    dry_run: bool,
+) -> Result<(), Error> {
     // Load the user's public account.
     let load_objects_timer = Instant::now();
 
-    let (user, ticker, tx_id) = get_user_ticker_from(account_id, db_dir.clone())?;
+    let (user, ticker, tx_id) = AccountIndex::lookup(index, account_id, storage)?;
     info!(
         "Validating account{{tx_id: {}, account_id: {}, user: {}, ticker: {}}}",
         tx_id,
@@ -357,14 +1605,12 @@ pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result
         user,
         ticker
     );
-    let ordered_user_account_tx: OrderedPubAccountTx = load_object(
-        db_dir.clone(),
+    let ordered_user_account_tx: OrderedPubAccountTx = storage.get(
         ON_CHAIN_DIR,
         COMMON_OBJECTS_DIR,
         &account_create_transaction_file(tx_id, &user, &ticker),
     )?;
 
-    let valid_asset_ids = get_asset_ids(db_dir.clone())?;
     timing!(
         "validator.account.load_objects",
         load_objects_timer,
@@ -374,10 +1620,12 @@ pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result
 
     // Validate the account.
     let validate_account_timer = Instant::now();
-    let account_validator = AccountValidator {};
-    account_validator
-        .verify(&ordered_user_account_tx.account_tx, &valid_asset_ids)
-        .map_err(|error| Error::LibraryError { error })?;
+    let printable_account_id = PrintableAccountId(account_id.encode()).to_string();
+    let already_validated = AccountIndex::with_validated(index, storage, |validated| {
+        ensure_account_is_unique(validated, &printable_account_id, &user, &ticker)?;
+        Ok(validated.account_id(&user, &ticker).is_some())
+    })?;
+    verify_account_tx(&ordered_user_account_tx.account_tx, valid_asset_ids)?;
 
     timing!(
         "validator.account",
@@ -386,26 +1634,34 @@ pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result
         "tx_id" => tx_id.to_string()
     );
 
+    // The balance of an account that is already validated may have changed since its creation,
+    // so the account is not saved again.
+    if dry_run || already_validated {
+        return Ok(());
+    }
+
     // On success save the public account as validated.
     let save_objects_timer = Instant::now();
     let ordered_account = OrderedPubAccount {
         pub_account: ordered_user_account_tx.account_tx.pub_account,
         last_processed_tx_counter: Some(tx_id),
     };
-    save_object(
-        db_dir.clone(),
+    storage.put(
         ON_CHAIN_DIR,
         &user,
         &user_public_account_file(&ticker),
         &ordered_account,
     )?;
-    save_object(
-        db_dir,
+    storage.put(
         ON_CHAIN_DIR,
         &user,
         &user_public_account_balance_file(&ticker),
         &ordered_user_account_tx.account_tx.initial_balance,
     )?;
+    AccountIndex::with_validated(index, storage, |validated| {
+        validated.insert(printable_account_id, user, ticker);
+        Ok(())
+    })?;
 
     timing!(
         "validator.account.save_objects",
@@ -417,119 +1673,200 @@ pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result
     Ok(())
 }
 
-fn process_transaction(
-    instruction: TransferInstruction,
-    sender_pub_account: PubAccount,
-    receiver_pub_account: PubAccount,
-    pending_balance: EncryptedAmount,
+/// Verifies the proofs of an account creation transaction. This is the cryptographic check of
+/// `validate_account`, without any of its IO, so a newly submitted account can be checked before
+/// it is saved.
+pub fn verify_account_tx(tx: &PubAccountTx, valid_asset_ids: &[Scalar]) -> Result<(), Error> {
+    AccountValidator {}
+        .verify(tx, valid_asset_ids)
+        .map_err(Error::from)
+}
+
+/// Returns `AccountFrozen` if the user's account for the ticker is frozen.
+fn ensure_not_frozen(
+    storage: &dyn Storage,
+    user: &str,
+    ticker: &String,
+    account_id: &EncryptedAssetId,
+) -> Result<(), Error> {
+    if is_account_frozen(storage, user, ticker)? {
+        return Err(Error::AccountFrozen {
+            account_id: PrintableAccountId(account_id.encode()).to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the failure to record when an account is frozen, or when its freeze flag can not be
+/// read.
+fn freeze_failure(error: &Error) -> ValidationFailure {
+    match error {
+        Error::AccountFrozen { account_id } => ValidationFailure::AccountFrozen {
+            account_id: account_id.clone(),
+        },
+        error => ValidationFailure::FreezeFlagUnreadable {
+            error: error.to_string(),
+        },
+    }
+}
+
+/// Returns the failure to record when an account can not be looked up. An account id that is
+/// missing from the account map is reported as `AccountNotFound`, so that a dangling reference
+/// can be told apart from a failure to read the map.
+fn lookup_failure(error: &Error) -> ValidationFailure {
+    match error {
+        Error::AccountIdNotFound { account_id } => ValidationFailure::AccountNotFound {
+            account_id: account_id.clone(),
+        },
+        _ => ValidationFailure::UserLookupFailed,
+    }
+}
+
+/// Verifies the proofs of a justified transfer against the sender's and receiver's accounts.
+/// This is the cryptographic check of `validate_transaction`, without any of its IO, so it can
+/// be called with accounts that are not on the chain.
+pub fn verify_transfer_core(
+    tx: &JustifiedTransferTx,
+    sender: &PubAccount,
+    receiver: &PubAccount,
+    pending_balance: &EncryptedAmount,
 ) -> Result<(), Error> {
     let mut rng = OsRng::default();
-    let tx = JustifiedTransferTx::decode(&mut &instruction.data[..]).unwrap();
-    let validator = TransactionValidator;
-    validator
-        .verify_transaction(
-            &tx,
-            &sender_pub_account,
-            &pending_balance,
-            &receiver_pub_account,
-            &[],
-            &mut rng,
-        )
-        .map_err(|error| Error::LibraryError { error })
+    TransactionValidator
+        .verify_transaction(tx, sender, pending_balance, receiver, &[], &mut rng)
+        .map_err(Error::from)
+}
+
+/// Returns true if the account was saved by a batch that included `tx_id`. The transactions up to
+/// the last validated tx_id are never validated again, so such an account already reflects the
+/// transaction.
+fn is_applied(account: &OrderedPubAccount, tx_id: u32) -> bool {
+    account
+        .last_processed_tx_counter
+        .filter(|counter| *counter >= tx_id)
+        .is_some()
 }
 
+/// Validates a justified transfer and, unless `options.dry_run` is set, saves its instruction as
+/// validated. An instruction that is already validated on file is only saved again if
+/// `options.force_rewrite` is set. The amounts of the accounts that already reflect the transfer
+/// are reported as persisted.
 pub fn validate_transaction(
-    db_dir: PathBuf,
+    storage: &dyn Storage,
+    index: Option<&AccountIndex>,
     tx: JustifiedTransferTx,
     mediator: String,
     pending_balance: EncryptedAmount,
     tx_id: u32,
+    options: ValidationOptions,
 ) -> (ValidationResult, ValidationResult) {
     let load_objects_timer = Instant::now();
-    // Load the transaction, mediator's account, and issuer's public account.
-
-    let (sender, _, _) = match get_user_ticker_from(
-        tx.finalized_data.init_data.memo.sender_account_id,
-        db_dir.clone(),
-    ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error("n/a", "n/a"),
-                ValidationResult::error("n/a", "n/a"),
-            );
-        }
-        Ok(ok) => ok,
-    };
-
-    let (receiver, ticker, _) = match get_user_ticker_from(
-        tx.finalized_data.init_data.memo.receiver_account_id,
-        db_dir.clone(),
-    ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error("n/a", "n/a"),
-                ValidationResult::error("n/a", "n/a"),
-            );
-        }
-        Ok(ok) => ok,
-    };
-
-    info!(
-        "Validating asset transfer{{tx_id: {}, sender: {}, receiver: {}, ticker:{}, mediator: {}}}",
-        tx_id, sender, receiver, ticker, mediator
-    );
-    let state = TransferTxState::Justification(TxSubstate::Started);
-
-    let mut instruction: TransferInstruction = match load_object(
-        db_dir.clone(),
-        ON_CHAIN_DIR,
-        COMMON_OBJECTS_DIR,
-        &confidential_transaction_file(tx_id, &mediator, state),
+    // Load the transaction, mediator's account, and issuer's public account.
+
+    let (sender, sender_ticker, _) = match AccountIndex::lookup(
+        index,
+        tx.finalized_data.init_data.memo.sender_account_id,
+        storage,
     ) {
         Err(error) => {
             error!("Error in validation of tx-{}: {:#?}", tx_id, error);
             return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
+                ValidationResult::failed("n/a", "n/a", lookup_failure(&error)),
+                ValidationResult::failed("n/a", "n/a", lookup_failure(&error)),
             );
         }
         Ok(ok) => ok,
     };
 
-    let sender_ordered_pub_account: OrderedPubAccount = match load_object(
-        db_dir.clone(),
-        ON_CHAIN_DIR,
-        &sender,
-        &user_public_account_file(&ticker),
+    let (receiver, ticker, _) = match AccountIndex::lookup(
+        index,
+        tx.finalized_data.init_data.memo.receiver_account_id,
+        storage,
     ) {
         Err(error) => {
             error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+            // The sender is known, so the transfer is counted as a failure of the sender's
+            // account instead of being dropped along with the unknown receiver.
             return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
+                ValidationResult::failed(&sender, &sender_ticker, lookup_failure(&error)),
+                ValidationResult::failed("n/a", "n/a", lookup_failure(&error)),
             );
         }
         Ok(ok) => ok,
     };
 
-    let receiver_ordered_pub_account: OrderedPubAccount = match load_object(
-        db_dir.clone(),
+    if let Err(error) = ensure_same_asset(&sender_ticker, &ticker) {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        let failure = asset_mismatch_failure(error);
+        return (
+            ValidationResult::failed(&sender, &sender_ticker, failure.clone()),
+            ValidationResult::failed(&receiver, &ticker, failure),
+        );
+    }
+
+    // The balances of a frozen account must not change, so neither leg of the transfer is applied.
+    let memo = &tx.finalized_data.init_data.memo;
+    for (user, account_id) in [
+        (&sender, memo.sender_account_id),
+        (&receiver, memo.receiver_account_id),
+    ]
+    .iter()
+    {
+        if let Err(error) = ensure_not_frozen(storage, user, &ticker, account_id) {
+            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+            let failure = freeze_failure(&error);
+            return (
+                ValidationResult::failed(&sender, &ticker, failure.clone()),
+                ValidationResult::failed(&receiver, &ticker, failure),
+            );
+        }
+    }
+
+    info!(
+        "Validating asset transfer{{tx_id: {}, sender: {}, receiver: {}, ticker:{}, mediator: {}}}",
+        tx_id, sender, receiver, ticker, mediator
+    );
+    let state = TransferTxState::Justification(TxSubstate::Started);
+
+    let mut instruction: TransferInstruction = match storage.get(
         ON_CHAIN_DIR,
-        &receiver,
-        &user_public_account_file(&ticker),
+        COMMON_OBJECTS_DIR,
+        &confidential_transaction_file(tx_id, &mediator, state),
     ) {
         Err(error) => {
             error!("Error in validation of tx-{}: {:#?}", tx_id, error);
             return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
+                ValidationResult::failed(&sender, &ticker, ValidationFailure::MissingObject),
+                ValidationResult::failed(&receiver, &ticker, ValidationFailure::MissingObject),
             );
         }
         Ok(ok) => ok,
     };
 
+    let sender_ordered_pub_account: OrderedPubAccount =
+        match storage.get(ON_CHAIN_DIR, &sender, &user_public_account_file(&ticker)) {
+            Err(error) => {
+                error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+                return (
+                    ValidationResult::failed(&sender, &ticker, ValidationFailure::MissingObject),
+                    ValidationResult::failed(&receiver, &ticker, ValidationFailure::MissingObject),
+                );
+            }
+            Ok(ok) => ok,
+        };
+
+    let receiver_ordered_pub_account: OrderedPubAccount =
+        match storage.get(ON_CHAIN_DIR, &receiver, &user_public_account_file(&ticker)) {
+            Err(error) => {
+                error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+                return (
+                    ValidationResult::failed(&sender, &ticker, ValidationFailure::MissingObject),
+                    ValidationResult::failed(&receiver, &ticker, ValidationFailure::MissingObject),
+                );
+            }
+            Ok(ok) => ok,
+        };
+
     timing!(
         "validator.issuance.load_objects",
         load_objects_timer,
@@ -537,22 +1874,38 @@ pub fn validate_transaction(
         "tx_id" => tx_id.to_string()
     );
 
+    // An interrupted batch may have saved some of its accounts already, and these accounts
+    // already reflect the transfer.
+    let sender_applied = is_applied(&sender_ordered_pub_account, tx_id);
+    let receiver_applied = is_applied(&receiver_ordered_pub_account, tx_id);
+
+    instruction.state = TransferTxState::Justification(TxSubstate::Validated);
+    let validated_file = confidential_transaction_file(tx_id, &sender, instruction.state);
+    // A re-run finds the instruction that it would save already validated on file.
+    let already_validated =
+        match storage.get::<TransferInstruction>(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &validated_file)
+        {
+            Err(_) => false,
+            Ok(saved) => saved.state.to_string() == instruction.state.to_string(),
+        };
+
     let validate_transaction_timer = Instant::now();
-    let _result = match process_transaction(
-        instruction.clone(),
-        sender_ordered_pub_account.pub_account,
-        receiver_ordered_pub_account.pub_account,
-        pending_balance,
-    ) {
-        Err(error) => {
+    // Once the transfer is applied to the sender's account, its proofs no longer match the
+    // sender's balance, but the transfer was verified before its instruction was validated.
+    if !(sender_applied && already_validated) {
+        if let Err(error) = verify_transfer_core(
+            &tx,
+            &sender_ordered_pub_account.pub_account,
+            &receiver_ordered_pub_account.pub_account,
+            &pending_balance,
+        ) {
             error!("Error in validation of tx-{}: {:#?}", tx_id, error);
             return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
+                ValidationResult::failed(&sender, &ticker, ValidationFailure::ProofInvalid),
+                ValidationResult::failed(&receiver, &ticker, ValidationFailure::ProofInvalid),
             );
         }
-        Ok(ok) => ok,
-    };
+    }
 
     timing!(
         "validator.transaction",
@@ -563,19 +1916,19 @@ pub fn validate_transaction(
 
     let save_objects_timer = Instant::now();
     // Save the transaction under the new state.
-    instruction.state = TransferTxState::Justification(TxSubstate::Validated);
-    if let Err(error) = save_object(
-        db_dir.clone(),
-        ON_CHAIN_DIR,
-        COMMON_OBJECTS_DIR,
-        &confidential_transaction_file(tx_id, &sender, instruction.state),
-        &instruction,
-    ) {
-        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return (
-            ValidationResult::error(&sender, &ticker),
-            ValidationResult::error(&receiver, &ticker),
-        );
+    if !options.dry_run && (!already_validated || options.force_rewrite) {
+        if let Err(error) = storage.put(
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &validated_file,
+            &instruction,
+        ) {
+            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+            return (
+                ValidationResult::failed(&sender, &ticker, ValidationFailure::SaveFailed),
+                ValidationResult::failed(&receiver, &ticker, ValidationFailure::SaveFailed),
+            );
+        }
     }
 
     timing!(
@@ -590,13 +1943,1001 @@ pub fn validate_transaction(
             user: sender,
             ticker: ticker.clone(),
             direction: Direction::Outgoing,
-            amount: Some(tx.finalized_data.init_data.memo.enc_amount_using_sender),
+            amount: Some(
+                tx.finalized_data
+                    .init_data
+                    .memo
+                    .enc_amount_using_sender
+                    .into(),
+            ),
+            failure: None,
+            persisted: sender_applied,
         },
         ValidationResult {
             user: receiver,
             ticker: ticker.clone(),
             direction: Direction::Incoming,
-            amount: Some(tx.finalized_data.init_data.memo.enc_amount_using_receiver),
+            amount: Some(
+                tx.finalized_data
+                    .init_data
+                    .memo
+                    .enc_amount_using_receiver
+                    .into(),
+            ),
+            failure: None,
+            persisted: receiver_applied,
         },
     )
 }
+
+/// Returns `DuplicateAccount` if a validated account already exists with the same id, but for a
+/// different user or ticker, or for the same user and ticker, but with a different id.
+/// Finding the same id under the same user and ticker means that the account is being revalidated.
+fn ensure_account_is_unique(
+    validated: &ValidatedAccounts,
+    account_id: &str,
+    user: &str,
+    ticker: &str,
+) -> Result<(), Error> {
+    let other_owner = validated
+        .owner(account_id)
+        .filter(|(owner, owner_ticker)| owner != user || owner_ticker != ticker);
+    let other_id = validated
+        .account_id(user, ticker)
+        .filter(|existing_id| existing_id.as_str() != account_id);
+    if other_owner.is_some() || other_id.is_some() {
+        return Err(Error::DuplicateAccount {
+            account_id: account_id.to_string(),
+            user: user.to_string(),
+            ticker: ticker.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemoryStorage;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        chain_setup::process_asset_id_creation,
+        construct_path, freeze_account, gen_seed,
+        justify::load_signing_keypair,
+        load_object, save_object, save_to_file, save_to_file_atomically,
+        test_chain::{TestChain, ISSUANCE_TX_ID},
+        unfreeze_account, user_frozen_account_file, user_secret_account_file,
+        OrderedTransferInstruction, USER_ACCOUNT_MAP, VALIDATED_PUBLIC_ACCOUNT_BALANCE_FILE,
+    };
+    use schnorrkel::Keypair;
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_only_signed_rejections_are_honoured() {
+        let storage = InMemoryStorage::default();
+        let storage: &dyn Storage = &storage;
+        let mediator = Keypair::generate_with(OsRng);
+        storage
+            .put(
+                ON_CHAIN_DIR,
+                "mike",
+                MEDIATOR_SIGNING_PUBLIC_KEY_FILE,
+                &mediator.public.to_bytes().to_vec(),
+            )
+            .unwrap();
+        let save_rejection = |rejection: &MediatorRejection| {
+            storage
+                .put(
+                    ON_CHAIN_DIR,
+                    COMMON_OBJECTS_DIR,
+                    &mediator_rejection_file(rejection.tx_id),
+                    rejection,
+                )
+                .unwrap();
+        };
+
+        // An unsigned rejection.
+        save_rejection(&MediatorRejection {
+            tx_id: 1,
+            mediator: String::from("mike"),
+            reason_code: None,
+            signature: vec![],
+        });
+        assert!(!is_rejected_by_mediator(storage, 1, "mike"));
+
+        // A rejection signed by someone else.
+        let intruder = Keypair::generate_with(OsRng);
+        save_rejection(&MediatorRejection::new(
+            2,
+            String::from("mike"),
+            None,
+            &intruder,
+        ));
+        assert!(!is_rejected_by_mediator(storage, 2, "mike"));
+
+        // A rejection whose reason was changed after it was signed.
+        let mut tampered = MediatorRejection::new(3, String::from("mike"), Some(1), &mediator);
+        tampered.reason_code = Some(2);
+        save_rejection(&tampered);
+        assert!(!is_rejected_by_mediator(storage, 3, "mike"));
+
+        // A rejection signed by the mediator.
+        save_rejection(&MediatorRejection::new(
+            4,
+            String::from("mike"),
+            Some(1),
+            &mediator,
+        ));
+        assert!(is_rejected_by_mediator(storage, 4, "mike"));
+        assert!(!is_rejected_by_mediator(storage, 4, "not-mike"));
+    }
+
+    #[test]
+    fn test_transfer_with_a_signed_rejection_is_skipped() {
+        let chain = TestChain::funded("signed_rejection");
+        let mediator = load_signing_keypair(chain.db_dir.clone(), &chain.mike).unwrap();
+        let intruder = Keypair::generate_with(OsRng);
+        let unsigned = |tx_id| MediatorRejection {
+            tx_id,
+            mediator: chain.mike.clone(),
+            reason_code: None,
+            signature: vec![],
+        };
+        let rejections = vec![
+            (ISSUANCE_TX_ID + 1, unsigned(ISSUANCE_TX_ID + 1), false),
+            (
+                ISSUANCE_TX_ID + 2,
+                MediatorRejection::new(ISSUANCE_TX_ID + 2, chain.mike.clone(), Some(1), &intruder),
+                false,
+            ),
+            (
+                ISSUANCE_TX_ID + 3,
+                MediatorRejection::new(ISSUANCE_TX_ID + 3, chain.mike.clone(), Some(1), &mediator),
+                true,
+            ),
+        ];
+
+        let (mut alice_balance, mut bob_balance) = (50, 0);
+        for (tx_id, rejection, honoured) in rejections {
+            chain.transfer(tx_id, 10);
+            save_object(
+                chain.db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                &mediator_rejection_file(tx_id),
+                &rejection,
+            )
+            .unwrap();
+            chain.validate();
+
+            if !honoured {
+                alice_balance -= 10;
+                bob_balance += 10;
+            }
+            assert_eq!(chain.balance(&chain.alice), alice_balance);
+            assert_eq!(chain.balance(&chain.bob), bob_balance);
+        }
+
+        // The skipped transfer is saved as rejected.
+        let rejected: TransferInstruction = load_object(
+            chain.db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &confidential_transaction_file(
+                ISSUANCE_TX_ID + 3,
+                &chain.alice,
+                TransferTxState::Justification(TxSubstate::Rejected),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            rejected.state.to_string(),
+            TransferTxState::Justification(TxSubstate::Rejected).to_string()
+        );
+    }
+
+    #[test]
+    fn test_verify_pipeline_is_lazy() {
+        let pulled = Cell::new(0);
+        let txs = (0..5).map(|_| CoreTransaction::Invalid).inspect(|_| {
+            pulled.set(pulled.get() + 1);
+        });
+
+        // Stop at the first failure.
+        let first_failure = verify_pipeline(
+            txs,
+            &InMemoryStorage::default(),
+            PathBuf::from("non-existent-db-dir"),
+        )
+        .find(|(_, result)| result.is_err());
+        assert!(first_failure.is_some());
+        assert_eq!(pulled.get(), 1);
+    }
+
+    #[test]
+    fn test_verify_pipeline_leaves_the_chain_untouched() {
+        let (chain, tx_id) = TestChain::with_transfer("verify_pipeline");
+        chain.issue(tx_id + 1, 10);
+        let storage: &dyn Storage = &FileStorage::new(chain.db_dir.clone());
+        let on_chain_objects = || {
+            let mut objects = vec![];
+            for owner in storage.owners(ON_CHAIN_DIR).unwrap() {
+                for name in storage.list(ON_CHAIN_DIR, &owner).unwrap() {
+                    let data = storage.get_bytes(ON_CHAIN_DIR, &owner, &name).unwrap();
+                    objects.push((owner.clone(), name, data));
+                }
+            }
+            objects.sort();
+            objects
+        };
+        let before = on_chain_objects();
+
+        let txs: Vec<CoreTransaction> = unverified_ready_iter(storage)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let results: Vec<(u32, Result<ValidationResult, Error>)> =
+            verify_pipeline(txs.into_iter(), storage, chain.db_dir.clone()).collect();
+
+        // The sender's and the receiver's results of the transfer, and the issuer's result.
+        let tx_ids: Vec<u32> = results.iter().map(|(tx_id, _)| *tx_id).collect();
+        assert_eq!(tx_ids, vec![tx_id, tx_id, tx_id + 1]);
+        for (_, result) in &results {
+            assert!(result.as_ref().unwrap().failure().is_none());
+        }
+        assert!(on_chain_objects() == before);
+        assert_eq!(last_verified_tx_id(storage), ISSUANCE_TX_ID as i32);
+    }
+
+    #[test]
+    fn test_transfer_of_a_frozen_account_is_rejected_until_unfrozen() {
+        let (chain, tx_id) = TestChain::with_transfer("frozen_account");
+        let db_dir = chain.db_dir.clone();
+        let storage: &dyn Storage = &FileStorage::new(db_dir.clone());
+        let (tx, mediator) = match unverified_ready_iter(storage).unwrap().next() {
+            Some(Ok(CoreTransaction::TransferJustify { tx, mediator, .. })) => (tx, mediator),
+            _ => panic!("Expected the justified transfer."),
+        };
+        let failures = || {
+            let (sender_result, receiver_result) = validate_transaction(
+                storage,
+                None,
+                tx.clone(),
+                mediator.clone(),
+                chain.encrypted_balance(&chain.alice),
+                tx_id,
+                ValidationOptions {
+                    dry_run: true,
+                    ..ValidationOptions::default()
+                },
+            );
+            (
+                sender_result.failure().cloned(),
+                receiver_result.failure().cloned(),
+            )
+        };
+        let is_frozen = |failure: &Option<ValidationFailure>| match failure {
+            Some(ValidationFailure::AccountFrozen { .. }) => true,
+            _ => false,
+        };
+
+        for user in &[&chain.alice, &chain.bob] {
+            freeze_account(db_dir.clone(), user, &chain.ticker).unwrap();
+            let (sender_failure, receiver_failure) = failures();
+            assert!(is_frozen(&sender_failure) && is_frozen(&receiver_failure));
+
+            unfreeze_account(db_dir.clone(), user, &chain.ticker).unwrap();
+            assert_eq!(failures(), (None, None));
+        }
+
+        // A freeze flag that can not be read fails the transfer with the reason.
+        storage
+            .put_bytes(
+                ON_CHAIN_DIR,
+                &chain.bob,
+                &user_frozen_account_file(&chain.ticker),
+                vec![],
+            )
+            .unwrap();
+        match failures() {
+            (Some(ValidationFailure::FreezeFlagUnreadable { error }), Some(_)) => {
+                assert!(!error.is_empty())
+            }
+            result => panic!("Expected an unreadable freeze flag, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_registry_rejects_transactions_without_a_verifier() {
+        let db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let storage = InMemoryStorage::default();
+        let registry = VerifierRegistry::default();
+        match registry.verify(&storage, None, db_dir, CoreTransaction::Invalid, true) {
+            Err(Error::TransactionIsNotReadyForValidation { .. }) => {}
+            _ => panic!("Expected the invalid transaction to have no verifier."),
+        }
+    }
+
+    #[test]
+    fn test_cross_asset_transfer_is_rejected() {
+        let (chain, tx_id) = TestChain::with_transfer("cross_asset_transfer");
+        let db_dir = chain.db_dir.clone();
+        let storage: &dyn Storage = &FileStorage::new(db_dir.clone());
+
+        // Move bob's account to another ticker, so that the transfer is between two assets.
+        let other_ticker = String::from("BETA");
+        for (file, other_file) in &[
+            (
+                user_public_account_file(&chain.ticker),
+                user_public_account_file(&other_ticker),
+            ),
+            (
+                user_public_account_balance_file(&chain.ticker),
+                user_public_account_balance_file(&other_ticker),
+            ),
+        ] {
+            let data = storage.get_bytes(ON_CHAIN_DIR, &chain.bob, file).unwrap();
+            storage
+                .put_bytes(ON_CHAIN_DIR, &chain.bob, other_file, data)
+                .unwrap();
+        }
+        let mut mapping = load_account_map(storage);
+        for (user, ticker, _) in mapping.values_mut() {
+            if user == &chain.bob {
+                *ticker = other_ticker.clone();
+            }
+        }
+        save_to_file(
+            db_dir.clone(),
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            USER_ACCOUNT_MAP,
+            &mapping,
+        )
+        .unwrap();
+        let balances = || {
+            (
+                storage
+                    .get_bytes(
+                        ON_CHAIN_DIR,
+                        &chain.alice,
+                        &user_public_account_balance_file(&chain.ticker),
+                    )
+                    .unwrap(),
+                storage
+                    .get_bytes(
+                        ON_CHAIN_DIR,
+                        &chain.bob,
+                        &user_public_account_balance_file(&other_ticker),
+                    )
+                    .unwrap(),
+            )
+        };
+        let before = balances();
+
+        let txs: Vec<CoreTransaction> = unverified_ready_iter(storage)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let results: Vec<(u32, Result<ValidationResult, Error>)> =
+            verify_pipeline(txs.into_iter(), storage, db_dir.clone()).collect();
+        assert_eq!(results.len(), 2);
+        for (result_tx_id, result) in &results {
+            assert_eq!(*result_tx_id, tx_id);
+            assert_eq!(
+                result.as_ref().unwrap().failure(),
+                Some(&ValidationFailure::AssetIdMismatch {
+                    sender_ticker: chain.ticker.clone(),
+                    receiver_ticker: other_ticker.clone(),
+                })
+            );
+        }
+
+        validate_all_pending(
+            db_dir,
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+        assert_eq!(last_verified_tx_id(storage), tx_id as i32);
+        assert!(balances() == before);
+        assert_eq!(chain.balance(&chain.alice), 50);
+    }
+
+    #[test]
+    fn test_incremental_validation_counts_the_new_transactions() {
+        let (chain, tx_id) = TestChain::with_transfer("incremental_validation");
+        assert_eq!(validate_incremental(chain.db_dir.clone()).unwrap(), 1);
+        assert_eq!(validate_incremental(chain.db_dir.clone()).unwrap(), 0);
+
+        chain.issue(tx_id + 1, 10);
+        chain.issue(tx_id + 2, 10);
+        assert_eq!(validate_incremental(chain.db_dir.clone()).unwrap(), 2);
+        assert_eq!(chain.balance(&chain.alice), 40);
+    }
+
+    #[test]
+    fn test_empty_batch_keeps_the_last_validated_tx_id() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/empty_batch");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+        std::fs::create_dir_all(construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            "",
+        ))
+        .unwrap();
+        save_to_file(
+            db_dir.clone(),
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            LAST_VALIDATED_TX_ID_FILE,
+            &5,
+        )
+        .unwrap();
+
+        validate_all_pending(
+            db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+        assert_eq!(last_verified_tx_id(&FileStorage::new(db_dir)), 5);
+    }
+
+    #[test]
+    fn test_partial_batch_skips_unreadable_transactions() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/partial_batch");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+        let mut tx_dir = db_dir.clone();
+        tx_dir.push(ON_CHAIN_DIR);
+        tx_dir.push(COMMON_OBJECTS_DIR);
+        std::fs::create_dir_all(tx_dir.clone()).unwrap();
+        tx_dir.push("tx_0_alice_unknown-state");
+        std::fs::write(tx_dir, b"").unwrap();
+
+        assert!(validate_all_pending(
+            db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default()
+        )
+        .is_err());
+        let skipped = validate_all_pending_partial(
+            db_dir,
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].tx_id, None);
+    }
+
+    #[test]
+    fn test_dry_run_reports_failures_and_undecryptable_balances() {
+        let (chain, _) = TestChain::with_transfer("dry_run");
+        let tx_file = construct_path(
+            chain.db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            "tx_9_alice_unknown-state",
+        );
+        std::fs::write(tx_file.clone(), b"").unwrap();
+        // The validator does not hold bob's secret account.
+        let secret_file = construct_path(
+            chain.db_dir.clone(),
+            OFF_CHAIN_DIR,
+            &chain.bob,
+            &user_secret_account_file(&chain.ticker),
+        );
+        std::fs::remove_file(secret_file).unwrap();
+
+        let (reports, failed) = validate_all_pending_dry_run(chain.db_dir.clone()).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].tx_id, None);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].user, chain.alice);
+        assert_eq!(reports[0].old_balance, Some(50));
+        assert_eq!(reports[0].new_balance, Some(20));
+        assert_eq!(reports[1].user, chain.bob);
+        assert_eq!(reports[1].old_balance, None);
+        assert_eq!(reports[1].new_balance, None);
+        assert_ne!(
+            reports[1].old_enc_balance.encode(),
+            reports[1].new_enc_balance.encode()
+        );
+
+        // Nothing is applied.
+        let storage: &dyn Storage = &FileStorage::new(chain.db_dir.clone());
+        assert_eq!(last_verified_tx_id(storage), ISSUANCE_TX_ID as i32);
+        assert_eq!(
+            chain.encrypted_balance(&chain.alice).encode(),
+            reports[0].old_enc_balance.encode()
+        );
+    }
+
+    /// Fails to save the account balances once `balance_saves_left` of them have been saved,
+    /// as if the validator was interrupted.
+    struct InterruptedStorage {
+        inner: InMemoryStorage,
+        balance_saves_left: AtomicUsize,
+    }
+
+    impl Storage for InterruptedStorage {
+        fn get_bytes(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<Vec<u8>, Error> {
+            self.inner.get_bytes(on_off_chain, owner, name)
+        }
+
+        fn put_bytes(
+            &self,
+            on_off_chain: &str,
+            owner: &str,
+            name: &str,
+            data: Vec<u8>,
+        ) -> Result<(), Error> {
+            if name.ends_with(VALIDATED_PUBLIC_ACCOUNT_BALANCE_FILE)
+                && self
+                    .balance_saves_left
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                        left.checked_sub(1)
+                    })
+                    .is_err()
+            {
+                return Err(Error::ObjectSaveError {
+                    error: std::io::Error::new(std::io::ErrorKind::Other, "interrupted"),
+                    path: PathBuf::from(name),
+                });
+            }
+            self.inner.put_bytes(on_off_chain, owner, name, data)
+        }
+
+        fn remove(&self, on_off_chain: &str, owner: &str, name: &str) -> Result<(), Error> {
+            self.inner.remove(on_off_chain, owner, name)
+        }
+
+        fn list(&self, on_off_chain: &str, owner: &str) -> Result<Vec<String>, Error> {
+            self.inner.list(on_off_chain, owner)
+        }
+
+        fn owners(&self, on_off_chain: &str) -> Result<Vec<String>, Error> {
+            self.inner.owners(on_off_chain)
+        }
+
+        fn lock(
+            &self,
+            on_off_chain: &str,
+            owner: &str,
+            name: &str,
+            mode: LockMode,
+        ) -> Result<StorageLock, Error> {
+            self.inner.lock(on_off_chain, owner, name, mode)
+        }
+    }
+
+    #[test]
+    fn test_interrupted_batch_is_resumed() {
+        let (chain, tx_id) = TestChain::with_transfer("interrupted_batch");
+        // Only the first of the two accounts of the transfer is saved.
+        let interrupted = InterruptedStorage {
+            inner: chain.in_memory(),
+            balance_saves_left: AtomicUsize::new(1),
+        };
+        assert!(validate_all_pending_with_storage(
+            &interrupted,
+            chain.db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .is_err());
+
+        let storage: &dyn Storage = &interrupted.inner;
+        let checkpoint = load_validation_checkpoint(storage, Some(tx_id));
+        assert_eq!(checkpoint.saved_accounts.len(), 1);
+        assert_eq!(last_verified_tx_id(storage), ISSUANCE_TX_ID as i32);
+
+        validate_all_pending_with_storage(
+            storage,
+            chain.db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+
+        let balance = |user: &str| {
+            let balance: EncryptedAmount = storage
+                .get(
+                    ON_CHAIN_DIR,
+                    user,
+                    &user_public_account_balance_file(&chain.ticker),
+                )
+                .unwrap();
+            let account_id = chain.account(user).pub_account.enc_asset_id;
+            debug_decrypt(account_id, balance, chain.db_dir.clone()).unwrap()
+        };
+        assert_eq!(last_verified_tx_id(storage), tx_id as i32);
+        assert_eq!(balance(&chain.alice), 20);
+        assert_eq!(balance(&chain.bob), 30);
+        assert!(storage
+            .get_bytes(
+                OFF_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                VALIDATION_CHECKPOINT_FILE
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_duplicate_account_is_rejected() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/duplicate_account");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+
+        let ticker = String::from("ACME");
+        let user = String::from("alice");
+        process_asset_id_creation(db_dir.clone(), vec![ticker.clone()]).unwrap();
+        for tx_id in 0..2 {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                ticker.clone(),
+                user.clone(),
+                false,
+                tx_id,
+                false,
+            )
+            .unwrap();
+        }
+        let storage: &dyn Storage = &FileStorage::new(db_dir.clone());
+        let account_id = |tx_id| {
+            let account_tx: OrderedPubAccountTx = storage
+                .get(
+                    ON_CHAIN_DIR,
+                    COMMON_OBJECTS_DIR,
+                    &account_create_transaction_file(tx_id, &user, &ticker),
+                )
+                .unwrap();
+            account_tx.account_tx.pub_account.enc_asset_id
+        };
+
+        validate_account(storage, None, account_id(0), false).unwrap();
+        match validate_account(storage, None, account_id(1), false) {
+            Err(Error::DuplicateAccount { .. }) => {}
+            result => panic!("Expected a duplicate account error, got {:?}", result),
+        }
+
+        // The first account is still the validated one and can be revalidated.
+        let account: OrderedPubAccount = storage
+            .get(ON_CHAIN_DIR, &user, &user_public_account_file(&ticker))
+            .unwrap();
+        assert_eq!(
+            account.pub_account.enc_asset_id.encode(),
+            account_id(0).encode()
+        );
+        validate_account(storage, None, account_id(0), false).unwrap();
+    }
+
+    #[test]
+    fn test_accounts_sharing_an_id_in_one_batch_are_not_both_validated() {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push("chain_dir/unittest/shared_account_id");
+        let _ = std::fs::remove_dir_all(db_dir.clone());
+
+        // The same seed results in the same account id.
+        let ticker = String::from("ACME");
+        let seed = gen_seed();
+        process_asset_id_creation(db_dir.clone(), vec![ticker.clone()]).unwrap();
+        for (tx_id, user) in ["alice", "bob"].iter().enumerate() {
+            process_create_account(
+                Some(seed.clone()),
+                db_dir.clone(),
+                ticker.clone(),
+                user.to_string(),
+                false,
+                tx_id as u32,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(
+            db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+
+        let storage: &dyn Storage = &FileStorage::new(db_dir);
+        let validated = ValidatedAccounts::load(storage).unwrap();
+        let account_id = validated
+            .account_id("bob", &ticker)
+            .expect("The last account created with the id is validated.");
+        assert_eq!(
+            validated.owner(account_id),
+            Some(&(String::from("bob"), ticker.clone()))
+        );
+        assert!(validated.account_id("alice", &ticker).is_none());
+    }
+
+    #[test]
+    fn test_concurrent_validators_do_not_apply_a_batch_twice() {
+        let chain = TestChain::new("concurrent_validators");
+        let db_dir = chain.db_dir.clone();
+        chain.issue(ISSUANCE_TX_ID, 50);
+
+        // A validator that does not wait for the lock fails fast.
+        let storage = FileStorage::new(db_dir.clone());
+        let checkpoint_lock = lock_checkpoint(&storage, LockMode::Wait).unwrap();
+        match validate_all_pending(
+            db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::FailFast,
+        ) {
+            Err(Error::LedgerLocked { .. }) => {}
+            result => panic!("Expected a ledger locked error, got {:?}", result),
+        }
+        drop(checkpoint_lock);
+
+        let validators: Vec<_> = (0..2)
+            .map(|_| {
+                let db_dir = db_dir.clone();
+                std::thread::spawn(move || {
+                    validate_all_pending(db_dir, ReconciliationStrategy::default(), LockMode::Wait)
+                })
+            })
+            .collect();
+        for validator in validators {
+            validator.join().unwrap().unwrap();
+        }
+
+        // The issuance is applied exactly once.
+        assert_eq!(chain.balance(&chain.alice), 50);
+    }
+
+    #[test]
+    fn test_rewind_reverts_an_issuance() {
+        let chain = TestChain::funded("rewind_validation");
+        assert_eq!(chain.balance(&chain.alice), 50);
+
+        rewind_validation(chain.db_dir.clone(), ISSUANCE_TX_ID - 1).unwrap();
+        assert_eq!(
+            last_verified_tx_id(&FileStorage::new(chain.db_dir.clone())),
+            ISSUANCE_TX_ID as i32 - 1
+        );
+        assert_eq!(chain.balance(&chain.alice), 0);
+
+        // The issuance is validated again in the next pass.
+        chain.validate();
+        assert_eq!(chain.balance(&chain.alice), 50);
+    }
+
+    #[test]
+    fn test_rewind_skips_unreadable_transactions() {
+        let chain = TestChain::funded("rewind_unreadable");
+        let storage: &dyn Storage = &FileStorage::new(chain.db_dir.clone());
+        let stale_file = confidential_transaction_file(
+            ISSUANCE_TX_ID,
+            &chain.bob,
+            TransferTxState::Initialization(TxSubstate::Started),
+        );
+        storage
+            .put_bytes(ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &stale_file, vec![])
+            .unwrap();
+
+        rewind_validation(chain.db_dir.clone(), ISSUANCE_TX_ID - 1).unwrap();
+        assert_eq!(last_verified_tx_id(storage), ISSUANCE_TX_ID as i32 - 1);
+        assert_eq!(chain.balance(&chain.alice), 0);
+    }
+
+    #[test]
+    fn test_validating_a_middle_range_does_not_apply_validated_transactions_again() {
+        let (chain, tx_id) = TestChain::with_transfer("middle_range");
+        chain.validate();
+        assert_eq!(chain.balance(&chain.alice), 20);
+        assert_eq!(chain.balance(&chain.bob), 30);
+
+        chain.issue(tx_id + 1, 10);
+        validate_range(
+            chain.db_dir.clone(),
+            ISSUANCE_TX_ID - 1,
+            tx_id + 1,
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+
+        // Only the new issuance is applied.
+        assert_eq!(chain.balance(&chain.alice), 30);
+        assert_eq!(chain.balance(&chain.bob), 30);
+    }
+
+    #[test]
+    fn test_validating_a_middle_range_only_changes_its_accounts() {
+        let (chain, tx_id) = TestChain::with_transfer("middle_range_accounts");
+        let carol = String::from("carol");
+        process_create_account(
+            Some(gen_seed()),
+            chain.db_dir.clone(),
+            chain.ticker.clone(),
+            carol.clone(),
+            false,
+            tx_id + 1,
+            false,
+        )
+        .unwrap();
+        process_issue_asset(
+            gen_seed(),
+            chain.db_dir.clone(),
+            carol.clone(),
+            chain.ticker.clone(),
+            10,
+            false,
+            tx_id + 2,
+            false,
+        )
+        .unwrap();
+        let storage: &dyn Storage = &FileStorage::new(chain.db_dir.clone());
+
+        // Only carol's account and her issuance are in the range, and alice's transfer before
+        // it is left pending.
+        validate_range(
+            chain.db_dir.clone(),
+            tx_id + 1,
+            tx_id + 2,
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+        assert_eq!(chain.balance(&chain.alice), 50);
+        assert_eq!(chain.balance(&chain.bob), 0);
+        assert_eq!(chain.balance(&carol), 10);
+        assert_eq!(last_verified_tx_id(storage), ISSUANCE_TX_ID as i32);
+
+        // The next pass applies the transfer before the range, but not the range again.
+        chain.validate();
+        assert_eq!(chain.balance(&chain.alice), 20);
+        assert_eq!(chain.balance(&chain.bob), 30);
+        assert_eq!(chain.balance(&carol), 10);
+        assert_eq!(last_verified_tx_id(storage), (tx_id + 2) as i32);
+    }
+
+    #[test]
+    fn test_transfer_with_a_missing_predecessor_is_not_validated() {
+        let (chain, tx_id) = TestChain::with_transfer("missing_predecessor");
+
+        // The sender's account reflected a transaction that is not on the chain when the
+        // transfer was initialized.
+        let init_file = confidential_transaction_file(
+            tx_id,
+            &chain.alice,
+            TransferTxState::Initialization(TxSubstate::Started),
+        );
+        let mut init: OrderedTransferInstruction = load_object(
+            chain.db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &init_file,
+        )
+        .unwrap();
+        init.ordering_state.last_processed_tx_counter = Some(tx_id + 4);
+        save_object(
+            chain.db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &init_file,
+            &init,
+        )
+        .unwrap();
+
+        match validate_all_pending(
+            chain.db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        ) {
+            Err(Error::MissingPredecessor {
+                tx_id: missing_tx_id,
+                predecessor,
+            }) => {
+                assert_eq!(missing_tx_id, tx_id);
+                assert_eq!(predecessor, tx_id + 4);
+            }
+            result => panic!("Expected a missing predecessor error, got {:?}", result),
+        }
+        assert_eq!(chain.balance(&chain.alice), 50);
+    }
+
+    #[test]
+    fn test_validation_in_memory_leaves_the_disk_untouched() {
+        let (chain, tx_id) = TestChain::with_transfer("in_memory_validation");
+        let storage = chain.in_memory();
+        validate_all_pending_with_storage(
+            &storage,
+            chain.db_dir.clone(),
+            ReconciliationStrategy::default(),
+            LockMode::default(),
+        )
+        .unwrap();
+
+        let storage: &dyn Storage = &storage;
+        let balance = |user: &str| {
+            let balance: EncryptedAmount = storage
+                .get(
+                    ON_CHAIN_DIR,
+                    user,
+                    &user_public_account_balance_file(&chain.ticker),
+                )
+                .unwrap();
+            let account_id = chain.account(user).pub_account.enc_asset_id;
+            debug_decrypt(account_id, balance, chain.db_dir.clone()).unwrap()
+        };
+        assert_eq!(last_verified_tx_id(storage), tx_id as i32);
+        assert_eq!(balance(&chain.alice), 20);
+        assert_eq!(balance(&chain.bob), 30);
+
+        // The transfer is still pending on disk.
+        let disk: &dyn Storage = &FileStorage::new(chain.db_dir.clone());
+        assert_eq!(last_verified_tx_id(disk), ISSUANCE_TX_ID as i32);
+        assert_eq!(chain.balance(&chain.alice), 50);
+        assert_eq!(chain.balance(&chain.bob), 0);
+    }
+
+    #[test]
+    fn test_unfinalized_transfer_is_reported_as_stuck() {
+        let chain = TestChain::new("stuck_accounts");
+        let db_dir = chain.db_dir.clone();
+        chain.issue(ISSUANCE_TX_ID, 50);
+        assert!(stuck_accounts(db_dir.clone(), 1).unwrap().is_empty());
+
+        // The receiver never finalizes this transfer.
+        chain.create_transfer(3, 30);
+
+        let ticker = chain.ticker.clone();
+        assert_eq!(
+            stuck_accounts(db_dir, 3).unwrap(),
+            vec![
+                (chain.alice.clone(), ticker.clone(), vec![3]),
+                (chain.bob.clone(), ticker, vec![3])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfer_to_an_unknown_account_is_not_dropped() {
+        let (chain, _) = TestChain::with_transfer("unknown_receiver");
+        let db_dir = chain.db_dir.clone();
+
+        // Drop bob from the account map, so that the transfer references an unknown account.
+        chain.forget_account(&chain.bob);
+
+        let result = validate_all_pending(
+            db_dir,
+            ReconciliationStrategy::HaltOnFailure,
+            LockMode::default(),
+        );
+        match result {
+            Err(Error::ReconciliationHalted { accounts }) => {
+                assert_eq!(accounts, vec![(chain.alice.clone(), chain.ticker.clone())])
+            }
+            _ => panic!("Expected the reconciliation to halt on the sender's account."),
+        }
+    }
+
+    #[test]
+    fn test_halted_reconciliation_does_not_apply_an_issuance() {
+        let (chain, tx_id) = TestChain::with_transfer("halted_issuance");
+        let db_dir = chain.db_dir.clone();
+        chain.issue(tx_id + 1, 10);
+
+        // Drop bob from the account map, so that alice's transfer fails.
+        chain.forget_account(&chain.bob);
+
+        assert!(validate_all_pending(
+            db_dir,
+            ReconciliationStrategy::HaltOnFailure,
+            LockMode::default(),
+        )
+        .is_err());
+        assert_eq!(chain.balance(&chain.alice), 50);
+    }
+}
@@ -0,0 +1,59 @@
+//! Property tests for the additive homomorphism of the `CipherText`, which the validator relies
+//! on when it folds the encrypted transfer amounts into the account balances.
+
+use codec::Encode;
+use cryptography::asset_proofs::{CipherText, CommitmentWitness, ElgamalSecretKey};
+use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar, traits::Identity};
+use proptest::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Keeps the plaintexts small enough for the brute-force decryption to stay fast.
+const MAX_VALUE: u32 = 10_000;
+
+fn encrypt(secret_key: &ElgamalSecretKey, value: u32, rng: &mut StdRng) -> CipherText {
+    let witness = CommitmentWitness::new(Scalar::from(value), Scalar::random(rng));
+    secret_key.get_public_key().encrypt(&witness)
+}
+
+proptest! {
+    #[test]
+    fn sum_of_ciphertexts_decrypts_to_sum_of_values(
+        a in 0..MAX_VALUE,
+        b in 0..MAX_VALUE,
+        seed in any::<[u8; 32]>(),
+    ) {
+        let mut rng = StdRng::from_seed(seed);
+        let secret_key = ElgamalSecretKey::new(Scalar::random(&mut rng));
+        let sum = encrypt(&secret_key, a, &mut rng) + encrypt(&secret_key, b, &mut rng);
+        prop_assert_eq!(secret_key.decrypt(&sum).unwrap(), a + b);
+    }
+
+    #[test]
+    fn difference_of_ciphertexts_decrypts_to_difference_of_values(
+        a in 0..MAX_VALUE,
+        b in 0..MAX_VALUE,
+        seed in any::<[u8; 32]>(),
+    ) {
+        let (a, b) = (std::cmp::max(a, b), std::cmp::min(a, b));
+        let mut rng = StdRng::from_seed(seed);
+        let secret_key = ElgamalSecretKey::new(Scalar::random(&mut rng));
+        let difference = encrypt(&secret_key, a, &mut rng) - encrypt(&secret_key, b, &mut rng);
+        prop_assert_eq!(secret_key.decrypt(&difference).unwrap(), a - b);
+    }
+
+    #[test]
+    fn adding_zero_leaves_the_ciphertext_unchanged(
+        a in 0..MAX_VALUE,
+        seed in any::<[u8; 32]>(),
+    ) {
+        let mut rng = StdRng::from_seed(seed);
+        let secret_key = ElgamalSecretKey::new(Scalar::random(&mut rng));
+        let cipher = encrypt(&secret_key, a, &mut rng);
+        let zero = CipherText {
+            x: RistrettoPoint::identity(),
+            y: RistrettoPoint::identity(),
+        };
+        prop_assert_eq!((cipher + zero).encode(), cipher.encode());
+        prop_assert_eq!(secret_key.decrypt(&(cipher + zero)).unwrap(), a);
+    }
+}
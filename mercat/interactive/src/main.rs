@@ -146,9 +146,7 @@ fn process_create_account(
     // Create the account.
     let secret_account = create_secret_account(&mut rng, ticker.clone())?;
 
-    let account_tx = AccountCreator
-        .create(&secret_account, &valid_asset_ids, &mut rng)
-        .map_err(|error| Error::LibraryError { error })?;
+    let account_tx = AccountCreator.create(&secret_account, &valid_asset_ids, &mut rng)?;
 
     // Save the artifacts to file.
     save_object(
@@ -258,17 +256,15 @@ pub fn process_create_tx(
             owner_enc_pub_key: sender_account.public.owner_enc_pub_key,
         },
     };
-    let asset_tx = ctx_sender
-        .create_transaction(
-            &pending_account,
-            &pending_balance,
-            &receiver_pub_account,
-            &mediator_account,
-            &[],
-            amount,
-            &mut rng,
-        )
-        .map_err(|error| Error::LibraryError { error })?;
+    let asset_tx = ctx_sender.create_transaction(
+        &pending_account,
+        &pending_balance,
+        &receiver_pub_account,
+        &mediator_account,
+        &[],
+        amount,
+        &mut rng,
+    )?;
 
     info!(
         "CLI log: Initialized Transaction as base64:\n{}\n",
@@ -310,9 +306,7 @@ pub fn process_finalize_tx(
 
     // Finalize the transaction.
     let receiver = CtxReceiver {};
-    let asset_tx = receiver
-        .finalize_transaction(tx, receiver_account.clone(), amount, &mut rng)
-        .map_err(|error| Error::LibraryError { error })?;
+    let asset_tx = receiver.finalize_transaction(tx, receiver_account.clone(), amount, &mut rng)?;
 
     // Save the artifacts to file.
     info!(
@@ -370,18 +364,16 @@ pub fn justify_asset_transfer_transaction(
     asset_id[..decoded.len()].copy_from_slice(&decoded);
     let asset_id = AssetId { id: asset_id };
 
-    let justified_tx = CtxMediator {}
-        .justify_transaction(
-            asset_tx.clone(),
-            &mediator_account.encryption_key,
-            &sender_pub_account,
-            &sender_balance,
-            &receiver_pub_account,
-            &[],
-            asset_id,
-            &mut rng,
-        )
-        .map_err(|error| Error::LibraryError { error })?;
+    let justified_tx = CtxMediator {}.justify_transaction(
+        asset_tx.clone(),
+        &mediator_account.encryption_key,
+        &sender_pub_account,
+        &sender_balance,
+        &receiver_pub_account,
+        &[],
+        asset_id,
+        &mut rng,
+    )?;
 
     info!(
         "CLI log: Justified Transaction as base64:\n{}\n",
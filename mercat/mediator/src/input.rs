@@ -174,6 +174,15 @@ pub struct JustifyTransferInfo {
     )]
     pub reject: bool,
 
+    /// An optional code for the reason of rejecting the transaction.
+    /// It is recorded on the chain along with the rejection.
+    #[structopt(
+        long,
+        requires = "reject",
+        help = "The code of the reason for rejecting the transaction."
+    )]
+    pub reject_reason: Option<u32>,
+
     /// An optional path to save the config used for this experiment.
     #[structopt(
         parse(from_os_str),
@@ -248,6 +257,7 @@ pub fn parse_input() -> Result<CLI, confy::ConfyError> {
                 mediator: cfg.mediator,
                 seed,
                 reject: cfg.reject,
+                reject_reason: cfg.reject_reason,
                 save_config: cfg.save_config.clone(),
                 cheat: cfg.cheat,
                 stdout: cfg.stdout,
@@ -7,7 +7,7 @@ mod input;
 use mercat_common::{
     errors::Error,
     init_print_logger,
-    justify::{justify_asset_transfer_transaction, process_create_mediator},
+    justify::{justify_asset_transfer_transaction, process_create_mediator, TransferRejection},
 };
 
 use env_logger;
@@ -41,7 +41,9 @@ fn main() {
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
             cfg.stdout,
             cfg.tx_id,
-            cfg.reject,
+            cfg.reject.then_some(TransferRejection {
+                reason_code: cfg.reject_reason,
+            }),
             cfg.cheat,
         )
         .unwrap(),
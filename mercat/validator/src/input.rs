@@ -1,5 +1,6 @@
 use confy;
 use log::info;
+use mercat_common::{LockMode, ReconciliationStrategy};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -17,6 +18,46 @@ pub struct CLI {
         long
     )]
     pub db_dir: Option<PathBuf>,
+
+    /// The first tx_id of the range of transactions to (re)validate. When a range is given,
+    /// only the transactions inside it are validated, and the last validated tx_id is left as
+    /// is.
+    #[structopt(
+        long,
+        requires = "to",
+        help = "The first transaction id of the range to validate (inclusive)."
+    )]
+    pub from: Option<u32>,
+
+    /// The last tx_id of the range of transactions to (re)validate.
+    #[structopt(
+        long,
+        requires = "from",
+        help = "The last transaction id of the range to validate (inclusive)."
+    )]
+    pub to: Option<u32>,
+
+    /// How to update the balance of an account that has failed transactions in this batch.
+    /// `skip-failed` ignores the failed transactions, `halt-on-failure` leaves all balances
+    /// untouched and exits with an error, and `revert-user` leaves all the accounts of the
+    /// affected users untouched.
+    #[structopt(
+        long,
+        default_value = "skip-failed",
+        possible_values = &["skip-failed", "halt-on-failure", "revert-user"],
+        help = "The strategy for updating the accounts that have failed transactions."
+    )]
+    pub reconciliation_strategy: ReconciliationStrategy,
+
+    /// What to do when another validator is updating the same database directory.
+    /// `wait` blocks until the other validator is done, and `fail-fast` exits with an error.
+    #[structopt(
+        long,
+        default_value = "wait",
+        possible_values = &["wait", "fail-fast"],
+        help = "What to do when another validator holds the lock on the ledger."
+    )]
+    pub lock_mode: LockMode,
 }
 
 pub fn parse_input() -> Result<CLI, confy::ConfyError> {
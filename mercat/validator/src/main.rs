@@ -5,7 +5,11 @@ mod input;
 use env_logger;
 use input::parse_input;
 use log::info;
-use mercat_common::{errors::Error, init_print_logger, validate::validate_all_pending};
+use mercat_common::{
+    errors::Error,
+    init_print_logger,
+    validate::{validate_all_pending, validate_range},
+};
 use metrics::timing;
 use std::time::Instant;
 
@@ -17,6 +21,17 @@ fn main() {
     let parse_arg_timer = Instant::now();
     let args = parse_input().unwrap();
     timing!("validator.argument_parse", parse_arg_timer, Instant::now());
-    validate_all_pending(args.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()).unwrap();
+    let db_dir = args.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap();
+    match (args.from, args.to) {
+        (Some(from), Some(to)) => validate_range(
+            db_dir,
+            from,
+            to,
+            args.reconciliation_strategy,
+            args.lock_mode,
+        )
+        .unwrap(),
+        _ => validate_all_pending(db_dir, args.reconciliation_strategy, args.lock_mode).unwrap(),
+    };
     info!("The program finished successfully.");
 }